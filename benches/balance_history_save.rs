@@ -0,0 +1,153 @@
+// throughput of balance_history writes as during initial sync: COPY into staging
+// compared with the previous multi-row VALUES insert
+//
+// needs a migrated database, PG* env vars as for the migration binary;
+// everything is written in transactions that are rolled back
+//
+//   cargo bench --bench balance_history_save
+use anyhow::Result;
+use lib::config::migration as migration_config;
+use lib::db::mappers::{balance_history, blocks_microblocks, unique_address, unique_assets};
+use lib::db::{AsyncDb, Db};
+use lib::waves::bu::balance_updates::BalanceHistory;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio_postgres::{types::ToSql, Transaction};
+
+const CHUNK_SIZES: &[usize] = &[1_000, 5_000, 20_000, 100_000];
+const ROWS_PER_BLOCK: usize = 200;
+const ADDRESSES: usize = 5_000;
+const ASSETS: usize = 50;
+const ITERATIONS: u32 = 5;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = migration_config::load()?;
+    let mut db = Db::new(&config.postgres).await?;
+
+    let first_uid = blocks_microblocks::get_max_uid(&db).await? + 1_000_000;
+
+    println!(
+        "{:>8} {:>14} {:>14}",
+        "rows", "values rows/s", "copy rows/s"
+    );
+
+    for &size in CHUNK_SIZES {
+        let mut values_time = Duration::ZERO;
+        let mut copy_time = Duration::ZERO;
+
+        for _ in 0..ITERATIONS {
+            values_time += run_once(&mut db, first_uid, size, Method::Values).await?;
+            copy_time += run_once(&mut db, first_uid, size, Method::Copy).await?;
+        }
+
+        let rows = (size as u32 * ITERATIONS) as f64;
+
+        println!(
+            "{:>8} {:>14.0} {:>14.0}",
+            size,
+            rows / values_time.as_secs_f64(),
+            rows / copy_time.as_secs_f64()
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+enum Method {
+    Values,
+    Copy,
+}
+
+// measures only the balance_history write, transaction is rolled back on drop
+async fn run_once(db: &mut Db, first_uid: i64, size: usize, method: Method) -> Result<Duration> {
+    let tr = db.transaction().await?;
+
+    let blocks: Vec<(i64, String, i32, i64)> = (0..(size + ROWS_PER_BLOCK - 1) / ROWS_PER_BLOCK)
+        .map(|i| {
+            let uid = first_uid + i as i64;
+            (uid, format!("bench_block_{uid}"), uid as i32, 0)
+        })
+        .collect();
+    blocks_microblocks::save_solidified_bulk(&tr, &blocks).await?;
+
+    let balances: Vec<BalanceHistory> = (0..size)
+        .map(|i| BalanceHistory {
+            block_uid: first_uid + (i / ROWS_PER_BLOCK) as i64,
+            address: format!("bench_address_{}", i % ADDRESSES),
+            asset_id: format!("bench_asset_{}", i % ASSETS),
+            amount: Decimal::from(i as i64 * 100),
+            amount_before: Decimal::from(i as i64 * 90),
+            block_height: 1,
+            tx_id: Some(format!("bench_tx_{i}")),
+        })
+        .collect();
+
+    let addresses: Vec<&str> = balances.iter().map(|b| b.address.as_str()).collect();
+    let assets: Vec<&str> = balances.iter().map(|b| b.asset_id.as_str()).collect();
+    let address_map = unique_address::merge_bulk(&tr, &addresses).await?;
+    let assets_map = unique_assets::merge_bulk(&tr, &assets).await?;
+
+    let started = Instant::now();
+
+    let saved = match method {
+        Method::Values => save_bulk_values(&tr, &balances, &assets_map, &address_map).await?,
+        Method::Copy => balance_history::save_bulk(&tr, &balances, &assets_map, &address_map)
+            .await?
+            .len(),
+    };
+
+    let elapsed = started.elapsed();
+
+    assert_eq!(saved, size);
+
+    Ok(elapsed)
+}
+
+// write path before COPY: numbers inlined into the query text in chunks of 5000, tx_id bound
+async fn save_bulk_values(
+    tr: &Transaction<'_>,
+    balances: &[BalanceHistory],
+    asset_ids: &HashMap<String, i64>,
+    address_ids: &HashMap<String, i64>,
+) -> Result<usize> {
+    let mut saved = 0;
+
+    for ch in balances.chunks(5000) {
+        let params: Vec<&(dyn ToSql + Sync)> =
+            ch.iter().map(|b| &b.tx_id as &(dyn ToSql + Sync)).collect();
+
+        let vals = ch
+            .iter()
+            .enumerate()
+            .map(|(idx, b)| {
+                format!(
+                    "({},{},{},{},{},{},{},${}::TEXT)",
+                    idx + 1,
+                    b.block_uid,
+                    b.amount,
+                    b.amount_before,
+                    b.amount - b.amount_before,
+                    address_ids[&b.address],
+                    asset_ids[&b.asset_id],
+                    idx + 1,
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let sql = format!("insert into balance_history(block_uid, amount, amount_before, delta, address_id, asset_id, tx_id)
+                                    select bm.uid, vals.amount, vals.amount_before, vals.delta, vals.address_id, vals.asset_id, vals.tx_id
+                                        from (values {vals}) as vals(chunk_uid, block_uid, amount, amount_before, delta, address_id, asset_id, tx_id)
+                                        inner join blocks_microblocks bm on bm.uid = vals.block_uid
+                                        order by chunk_uid
+                                    for update
+                                returning uid");
+
+        saved += tr.query(&sql, &params).await?.len();
+    }
+
+    Ok(saved)
+}
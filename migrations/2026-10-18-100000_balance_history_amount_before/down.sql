@@ -0,0 +1,2 @@
+ALTER TABLE balance_history DROP COLUMN delta;
+ALTER TABLE balance_history DROP COLUMN amount_before;
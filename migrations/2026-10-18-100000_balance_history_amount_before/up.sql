@@ -0,0 +1,4 @@
+-- both columns are null for rows stored before they were introduced;
+-- no defaults so the columns are added without rewriting the table
+ALTER TABLE balance_history ADD COLUMN amount_before numeric(100);
+ALTER TABLE balance_history ADD COLUMN delta numeric(100);
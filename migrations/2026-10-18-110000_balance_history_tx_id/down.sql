@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS bh_address_block_uid_uid;
+ALTER TABLE balance_history DROP COLUMN tx_id;
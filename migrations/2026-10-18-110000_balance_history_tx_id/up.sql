@@ -0,0 +1,5 @@
+-- base58 id of the transaction caused the change;
+-- null for block level changes (miner reward, fees) and for rows stored before the column was introduced
+ALTER TABLE balance_history ADD COLUMN tx_id TEXT;
+
+CREATE INDEX IF NOT EXISTS bh_address_block_uid_uid on balance_history(address_id, block_uid desc, uid desc);
@@ -0,0 +1 @@
+drop table leasing_history;
@@ -0,0 +1,11 @@
+create table leasing_history (
+    uid BIGINT GENERATED BY DEFAULT AS IDENTITY CONSTRAINT leasing_history_uid_pkey PRIMARY KEY,
+    block_uid BIGINT NOT NULL CONSTRAINT leasing_history_block_uid_fkey REFERENCES blocks_microblocks (uid) ON DELETE CASCADE,
+    address_id BIGINT NOT NULL,
+    lease_in numeric(100) NOT NULL,
+    lease_out numeric(100) NOT NULL,
+    tx_id TEXT
+);
+
+CREATE INDEX IF NOT EXISTS lh_address_block_uid on leasing_history(address_id, block_uid desc);
+CREATE INDEX IF NOT EXISTS lh_block_uid on leasing_history(block_uid);
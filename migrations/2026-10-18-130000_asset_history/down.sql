@@ -0,0 +1 @@
+drop table asset_history;
@@ -0,0 +1,14 @@
+create table asset_history (
+    uid BIGINT GENERATED BY DEFAULT AS IDENTITY CONSTRAINT asset_history_uid_pkey PRIMARY KEY,
+    block_uid BIGINT NOT NULL CONSTRAINT asset_history_block_uid_fkey REFERENCES blocks_microblocks (uid) ON DELETE CASCADE,
+    asset_id BIGINT NOT NULL,
+    quantity numeric(100) NOT NULL,
+    decimals INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    reissuable BOOLEAN NOT NULL,
+    script BYTEA,
+    tx_id TEXT
+);
+
+CREATE INDEX IF NOT EXISTS ah_asset_block_uid on asset_history(asset_id, block_uid desc);
+CREATE INDEX IF NOT EXISTS ah_block_uid on asset_history(block_uid);
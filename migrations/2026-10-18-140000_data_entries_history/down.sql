@@ -0,0 +1,2 @@
+delete from safe_heights where table_name = 'data_entries_history';
+drop table data_entries_history;
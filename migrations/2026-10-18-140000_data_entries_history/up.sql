@@ -0,0 +1,20 @@
+create table data_entries_history (
+    uid BIGINT GENERATED BY DEFAULT AS IDENTITY CONSTRAINT data_entries_history_uid_pkey PRIMARY KEY,
+    block_uid BIGINT NOT NULL CONSTRAINT data_entries_history_block_uid_fkey REFERENCES blocks_microblocks (uid) ON DELETE CASCADE,
+    address_id BIGINT NOT NULL,
+    key TEXT NOT NULL,
+    -- all values are null when the entry is deleted
+    int_val BIGINT,
+    bool_val BOOLEAN,
+    bin_val BYTEA,
+    str_val TEXT,
+    tx_id TEXT
+);
+
+CREATE INDEX IF NOT EXISTS deh_address_key_block_uid on data_entries_history(address_id, key, block_uid desc);
+CREATE INDEX IF NOT EXISTS deh_block_uid on data_entries_history(block_uid);
+
+-- the table is filled from the current height, so it must not lower the safe height on restart
+insert into safe_heights(table_name, height)
+    select 'data_entries_history', coalesce(min(height), 0) from safe_heights
+on conflict(table_name) do nothing;
@@ -0,0 +1,8 @@
+create table ingest_filters (
+    uid BIGINT GENERATED BY DEFAULT AS IDENTITY CONSTRAINT ingest_filters_uid_pkey PRIMARY KEY,
+    kind TEXT NOT NULL CONSTRAINT ingest_filters_kind_check CHECK (kind in ('asset', 'address')),
+    mode TEXT NOT NULL CONSTRAINT ingest_filters_mode_check CHECK (mode in ('include', 'exclude')),
+    value TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS ingest_filters_kind_mode_value_uix on ingest_filters(kind, mode, value);
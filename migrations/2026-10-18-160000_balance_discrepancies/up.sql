@@ -0,0 +1,14 @@
+create table balance_discrepancies (
+    uid BIGINT GENERATED BY DEFAULT AS IDENTITY CONSTRAINT balance_discrepancies_uid_pkey PRIMARY KEY,
+    block_uid BIGINT NOT NULL CONSTRAINT balance_discrepancies_block_uid_fkey REFERENCES blocks_microblocks (uid) ON DELETE CASCADE,
+    address_id BIGINT NOT NULL,
+    asset_id BIGINT NOT NULL,
+    -- last stored amount of the pair
+    expected_amount numeric(100) NOT NULL,
+    amount_before numeric(100) NOT NULL,
+    tx_id TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS bd_block_uid on balance_discrepancies(block_uid);
+CREATE INDEX IF NOT EXISTS bd_address_asset on balance_discrepancies(address_id, asset_id);
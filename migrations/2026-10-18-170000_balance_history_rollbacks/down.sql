@@ -0,0 +1,2 @@
+drop table balance_history_rollbacks;
+alter table blocks_rollbacks drop column created_at;
@@ -0,0 +1,19 @@
+alter table blocks_rollbacks add column created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+create table balance_history_rollbacks (
+    uid BIGINT GENERATED BY DEFAULT AS IDENTITY CONSTRAINT balance_history_rollbacks_uid_pkey PRIMARY KEY,
+    rollback_uid BIGINT NOT NULL CONSTRAINT balance_history_rollbacks_rollback_uid_fkey REFERENCES blocks_rollbacks (uid) ON DELETE CASCADE,
+    -- uids of removed balance_history and blocks_microblocks rows
+    bh_uid BIGINT NOT NULL,
+    block_uid BIGINT NOT NULL,
+    block_id TEXT NOT NULL,
+    height INTEGER NOT NULL,
+    address_id BIGINT NOT NULL,
+    asset_id BIGINT NOT NULL,
+    amount numeric(100),
+    amount_before numeric(100),
+    delta numeric(100),
+    tx_id TEXT
+);
+
+CREATE INDEX IF NOT EXISTS bhr_rollback_uid_uid on balance_history_rollbacks(rollback_uid, uid);
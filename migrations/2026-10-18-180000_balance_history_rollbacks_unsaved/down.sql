@@ -0,0 +1,3 @@
+delete from balance_history_rollbacks where bh_uid is null or block_id is null;
+alter table balance_history_rollbacks alter column bh_uid set not null;
+alter table balance_history_rollbacks alter column block_id set not null;
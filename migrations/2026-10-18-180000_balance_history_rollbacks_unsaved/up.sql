@@ -0,0 +1,4 @@
+-- rows dropped by the consumer before they were saved have no balance_history uid,
+-- their block is already deleted when they are archived
+alter table balance_history_rollbacks alter column bh_uid drop not null;
+alter table balance_history_rollbacks alter column block_id drop not null;
@@ -3,10 +3,24 @@ use consumer::SETTINGS;
 use lib::consumer;
 use lib::db::mappers::distribution_task;
 use lib::db::*;
+use tokio::select;
 use wavesexchange_log::info;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // the lock is held by this connection until the process exits
+    let lock_db = Db::new(&SETTINGS.config.postgres)
+        .await
+        .expect("can't connect to postgres");
+
+    consumer::lock::acquire(
+        &lock_db,
+        mappers::advisory_locks::CONSUMER_LOCK_KEY,
+        "consumer",
+        SETTINGS.config.lock_wait,
+    )
+    .await?;
+
     let mut db = Db::new(&SETTINGS.config.postgres)
         .await
         .expect("can't connect to postgres");
@@ -21,7 +35,15 @@ async fn main() -> Result<()> {
 
     drop(db);
 
-    consumer::run(SETTINGS.config.blockchain_updates_url.clone(), start_height).await
+    // the consumer is stopped at once when the lock is lost, a standby may already be writing
+    let res = select! {
+        res = consumer::run(consumer::new_event_source(), start_height) => res,
+        res = consumer::lock::watch(&lock_db, "consumer") => res,
+    };
+
+    drop(lock_db);
+
+    res
 }
 
 async fn init_db_data(db: &mut Db) -> Result<(), anyhow::Error> {
@@ -69,6 +91,9 @@ async fn init_db_data(db: &mut Db) -> Result<(), anyhow::Error> {
     tr.query("update safe_heights set height = $1", &[&new_safe_height])
         .await?;
 
+    // blocks loaded by backfill have explicit uids
+    mappers::blocks_microblocks::reset_uid_sequence(&tr).await?;
+
     tr.commit().await?;
 
     Ok(())
@@ -0,0 +1,32 @@
+use anyhow::{anyhow, Result};
+use lib::consumer::{self, SETTINGS};
+use lib::db::*;
+use std::env;
+use tokio::select;
+
+// usage: reindex <from_height> <to_height>
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+
+    let (from_height, to_height) = match &args[..] {
+        [_, from, to] => (from.parse::<i32>()?, to.parse::<i32>()?),
+        _ => return Err(anyhow!("usage: reindex <from_height> <to_height>")),
+    };
+
+    // reindexes of overlapping ranges would delete rows of each other
+    let lock_db = Db::new(&SETTINGS.config.postgres).await?;
+
+    consumer::lock::acquire(
+        &lock_db,
+        mappers::advisory_locks::REINDEX_LOCK_KEY,
+        "reindex",
+        SETTINGS.config.lock_wait,
+    )
+    .await?;
+
+    select! {
+        res = consumer::reindex::run(from_height, to_height) => res,
+        res = consumer::lock::watch(&lock_db, "reindex") => res,
+    }
+}
@@ -0,0 +1,26 @@
+use anyhow::Result;
+use lib::config::verify as verify_config;
+use lib::verify;
+use wavesexchange_log::error;
+
+// prints json report to stdout, exit code 1 on mismatches
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = verify_config::load()?;
+
+    let report = verify::run(&config).await?;
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    if !report.is_ok() {
+        error!(
+            "verify failed: {} mismatches, {} errors of {} checked",
+            report.mismatches.len(),
+            report.errors.len(),
+            report.checked
+        );
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
@@ -12,4 +12,7 @@ pub enum AppError {
 
     #[error("ValidationErrorCustom: {0}")]
     ValidationErrorCustom(String),
+
+    #[error("NotFound: {0}")]
+    NotFound(String),
 }
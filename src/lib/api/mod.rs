@@ -45,20 +45,151 @@ pub struct BalanceQuery {
     pub address_asset_pairs: Vec<BalanceEntry>,
 }
 
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct AddressesQuery {
+    pub addresses: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LeasingResponseItem {
+    pub address: String,
+    pub lease_in: Decimal,
+    pub lease_out: Decimal,
+    pub block_height: i32,
+    pub block_timestamp: ApiDate,
+}
+
+// regular WAVES balance and balances derived from it with leasing
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WavesBalanceItem {
+    pub address: String,
+    pub regular: Decimal,
+    // regular - lease_out
+    pub available: Decimal,
+    // regular + lease_in - lease_out
+    pub effective: Decimal,
+    pub lease_in: Decimal,
+    pub lease_out: Decimal,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BalanceResponseItem {
     pub address: String,
     pub asset_id: String,
     pub amount: Decimal,
+    // not known for changes stored before amount_before was recorded
+    pub amount_before: Option<Decimal>,
+    pub delta: Option<Decimal>,
+    pub block_height: i32,
+    pub block_timestamp: ApiDate,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BalanceChangeItem {
+    #[serde(skip_serializing)]
+    pub uid: i64,
+    #[serde(skip_serializing)]
+    pub block_uid: i64,
+    pub address: String,
+    pub asset_id: String,
+    pub amount: Decimal,
+    pub amount_before: Option<Decimal>,
+    pub delta: Option<Decimal>,
+    // None for block level changes (miner reward, fees)
+    pub tx_id: Option<String>,
+    pub block_height: i32,
+    pub block_timestamp: ApiDate,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AssetItem {
+    pub asset_id: String,
+    pub name: String,
+    pub decimals: i32,
+    pub quantity: Decimal,
+    pub reissuable: bool,
+    // base64
+    pub script: Option<String>,
+    pub block_height: i32,
+    pub block_timestamp: ApiDate,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AssetSupplyItem {
+    #[serde(skip_serializing)]
+    pub uid: i64,
+    #[serde(skip_serializing)]
+    pub block_uid: i64,
+    pub quantity: Decimal,
+    pub delta: Decimal,
+    pub tx_id: Option<String>,
     pub block_height: i32,
     pub block_timestamp: ApiDate,
 }
 
+// same representation as in node api, binary is "base64:..." encoded
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum DataEntryValue {
+    Integer(i64),
+    Boolean(bool),
+    Binary(String),
+    String(String),
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DataEntryItem {
+    #[serde(skip_serializing)]
+    pub uid: i64,
+    #[serde(skip_serializing)]
+    pub block_uid: i64,
+    pub address: String,
+    pub key: String,
+    // type and value are absent for deleted entries
+    #[serde(flatten)]
+    pub value: Option<DataEntryValue>,
+    pub tx_id: Option<String>,
+    pub block_height: i32,
+    pub block_timestamp: ApiDate,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RollbackItem {
+    pub uid: i64,
+    // block the chain was rolled back to
+    pub block_id: String,
+    // height of the last removed block
+    pub max_height: i32,
+    pub deleted_blocks: i32,
+    pub balance_changes: i64,
+    pub created_at: ApiDate,
+}
+
+// balance change removed by rollback
+#[derive(Debug, Serialize, Clone)]
+pub struct RolledBackBalanceItem {
+    #[serde(skip_serializing)]
+    pub uid: i64,
+    pub address: String,
+    pub asset_id: String,
+    pub amount: Option<Decimal>,
+    pub amount_before: Option<Decimal>,
+    pub delta: Option<Decimal>,
+    pub tx_id: Option<String>,
+    // None for rows dropped before they were saved
+    pub block_id: Option<String>,
+    pub block_height: i32,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BalanceResponseAggItem {
     pub date_stamp: ApiDate,
     pub amount_begin: Decimal,
     pub amount_end: Decimal,
+    // None when some changes of the day were stored before delta was introduced
+    pub delta: Option<Decimal>,
+    pub inflow: Option<Decimal>,
+    pub outflow: Option<Decimal>,
 }
 
 impl BalanceQuery {
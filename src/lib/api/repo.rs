@@ -1,13 +1,15 @@
 use super::{
-    error::AppError, AssetDistributionItem, BalanceEntry, BalanceQuery, BalanceResponseAggItem,
-    BalanceResponseItem,
+    error::AppError, AddressesQuery, AssetDistributionItem, AssetItem, AssetSupplyItem,
+    BalanceChangeItem, BalanceEntry, BalanceQuery, BalanceResponseAggItem, BalanceResponseItem,
+    DataEntryItem, DataEntryValue, LeasingResponseItem, RollbackItem, RolledBackBalanceItem,
+    WavesBalanceItem,
 };
 use crate::{
     api::server::DEFAULT_LIMIT,
     db::{mappers::distribution_task, PooledDb},
 };
 use chrono::{DateTime, NaiveDateTime, Utc};
-use futures::future::try_join_all;
+use futures::{future::try_join_all, try_join};
 use postgres_types::ToSql;
 use rust_decimal::Decimal;
 use serde::Serialize;
@@ -144,6 +146,48 @@ pub async fn get_balances_by_pairs(
     Ok(res)
 }
 
+pub async fn get_leasing_by_addresses(
+    db: &PooledDb,
+    uid: &i64,
+    req: &AddressesQuery,
+) -> Result<Vec<LeasingResponseItem>, AppError> {
+    let fs: Vec<_> = req
+        .addresses
+        .iter()
+        .map(|a| leasing_query(&db, &uid, &a))
+        .collect();
+
+    let items = try_join_all(fs)
+        .await
+        .map_err(|err| AppError::DbError(err.to_string()))?;
+
+    let res = items
+        .into_iter()
+        .filter(|i| i.is_some())
+        .map(|i| i.unwrap())
+        .collect();
+
+    Ok(res)
+}
+
+pub async fn get_waves_balances_by_addresses(
+    db: &PooledDb,
+    uid: &i64,
+    req: &AddressesQuery,
+) -> Result<Vec<WavesBalanceItem>, AppError> {
+    let fs: Vec<_> = req
+        .addresses
+        .iter()
+        .map(|a| waves_balance_query(&db, &uid, &a))
+        .collect();
+
+    let items = try_join_all(fs)
+        .await
+        .map_err(|err| AppError::DbError(err.to_string()))?;
+
+    Ok(items)
+}
+
 pub async fn asset_distribution_task_by_asset_id_height(
     db: &PooledDb,
     asset_id: &String,
@@ -336,12 +380,12 @@ pub async fn last_solidified_height(db: &PooledDb) -> Result<u32, anyhow::Error>
     Ok(h as u32)
 }
 
-async fn balance_query(
+pub(crate) async fn balance_query(
     db: &PooledDb,
     uid: &i64,
     e: &BalanceEntry,
 ) -> Result<Option<BalanceResponseItem>, anyhow::Error> {
-    let sql = "select ad.address, ast.asset_id, b.amount, bm.height block_height, to_timestamp(bm.time_stamp/1000) block_timestamp
+    let sql = "select ad.address, ast.asset_id, b.amount, bm.height block_height, to_timestamp(bm.time_stamp/1000) block_timestamp, b.amount_before, b.delta
             from balance_history b 
                 inner join blocks_microblocks bm on b.block_uid = bm.uid
                 inner join unique_assets ast on b.asset_id = ast.uid
@@ -370,11 +414,439 @@ async fn balance_query(
         amount: rows[0].get(2),
         block_height: rows[0].get(3),
         block_timestamp: rows[0].get(4),
+        amount_before: rows[0].get(5),
+        delta: rows[0].get(6),
+    };
+
+    Ok(Some(ret))
+}
+
+pub(crate) async fn leasing_query(
+    db: &PooledDb,
+    uid: &i64,
+    address: &String,
+) -> Result<Option<LeasingResponseItem>, anyhow::Error> {
+    let sql = "select ad.address, l.lease_in, l.lease_out, bm.height block_height, to_timestamp(bm.time_stamp/1000) block_timestamp
+            from leasing_history l
+                inner join blocks_microblocks bm on l.block_uid = bm.uid
+                inner join unique_address ad on l.address_id = ad.uid
+            where l.block_uid <= $1
+                and l.address_id = (select uid from unique_address where address = $2)
+            order by l.block_uid desc, l.uid desc
+            limit 1";
+
+    let conn = conn!(db);
+    let params: Vec<&(dyn ToSql + Sync)> = vec![&uid, &address];
+
+    let rows = conn
+        .query(sql, &params)
+        .await
+        .map_err(|err| AppError::DbError(err.to_string()))?;
+
+    if rows.len() < 1 {
+        return Ok(None);
+    }
+
+    let ret = LeasingResponseItem {
+        address: rows[0].get(0),
+        lease_in: rows[0].get(1),
+        lease_out: rows[0].get(2),
+        block_height: rows[0].get(3),
+        block_timestamp: rows[0].get(4),
+    };
+
+    Ok(Some(ret))
+}
+
+pub(crate) async fn waves_balance_query(
+    db: &PooledDb,
+    uid: &i64,
+    address: &String,
+) -> Result<WavesBalanceItem, anyhow::Error> {
+    let waves = BalanceEntry {
+        address: address.clone(),
+        asset_id: "WAVES".into(),
+    };
+
+    let (balance, leasing) = try_join!(
+        balance_query(&db, &uid, &waves),
+        leasing_query(&db, &uid, &address)
+    )?;
+
+    let regular = balance.map(|b| b.amount).unwrap_or_default();
+    let (lease_in, lease_out) = leasing
+        .map(|l| (l.lease_in, l.lease_out))
+        .unwrap_or_default();
+
+    Ok(WavesBalanceItem {
+        address: address.clone(),
+        regular,
+        available: regular - lease_out,
+        effective: regular + lease_in - lease_out,
+        lease_in,
+        lease_out,
+    })
+}
+
+// newest first; after is (block_uid, uid) of the last item of the previous page
+pub async fn balance_changes_by_address(
+    db: &PooledDb,
+    address: &String,
+    asset_id: Option<&String>,
+    uid: &i64,
+    after: Option<(i64, i64)>,
+) -> Result<(Vec<BalanceChangeItem>, bool, Option<String>), AppError> {
+    let sql = "select ad.address, ast.asset_id, b.amount, b.amount_before, b.delta, b.tx_id, bm.height block_height, to_timestamp(bm.time_stamp/1000) block_timestamp, b.block_uid, b.uid
+            from balance_history b
+                inner join blocks_microblocks bm on b.block_uid = bm.uid
+                inner join unique_assets ast on b.asset_id = ast.uid
+                inner join unique_address ad on b.address_id = ad.uid
+            where b.address_id = (select uid from unique_address where address = $1)
+                and b.block_uid <= $2
+                and (b.block_uid, b.uid) < ($3, $4)
+                and ($5::TEXT is null or b.asset_id = (select uid from unique_assets where asset_id = $5))
+            order by b.block_uid desc, b.uid desc
+            limit $6";
+
+    let (after_block_uid, after_uid) =
+        after.unwrap_or((super::PG_MAX_BIGINT, super::PG_MAX_BIGINT));
+
+    let conn = conn!(db);
+    let params: Vec<&(dyn ToSql + Sync)> = vec![
+        &address,
+        &uid,
+        &after_block_uid,
+        &after_uid,
+        &asset_id,
+        &(DEFAULT_LIMIT + 1),
+    ];
+
+    let mut rows: Vec<BalanceChangeItem> = conn
+        .query(sql, &params)
+        .await
+        .map_err(|err| AppError::DbError(err.to_string()))?
+        .iter()
+        .map(|r| BalanceChangeItem {
+            address: r.get(0),
+            asset_id: r.get(1),
+            amount: r.get(2),
+            amount_before: r.get(3),
+            delta: r.get(4),
+            tx_id: r.get(5),
+            block_height: r.get(6),
+            block_timestamp: r.get(7),
+            block_uid: r.get(8),
+            uid: r.get(9),
+        })
+        .collect();
+
+    let has_next_page = rows.len() > DEFAULT_LIMIT as usize;
+    if has_next_page {
+        rows.pop();
+    }
+
+    let last_cursor = rows.last().map(|r| format!("{}_{}", r.block_uid, r.uid));
+
+    Ok((rows, has_next_page, last_cursor))
+}
+
+pub async fn asset_query(
+    db: &PooledDb,
+    uid: &i64,
+    asset_id: &String,
+) -> Result<Option<AssetItem>, AppError> {
+    let sql = "select ast.asset_id, ah.name, ah.decimals, ah.quantity, ah.reissuable, ah.script, bm.height block_height, to_timestamp(bm.time_stamp/1000) block_timestamp
+            from asset_history ah
+                inner join blocks_microblocks bm on ah.block_uid = bm.uid
+                inner join unique_assets ast on ah.asset_id = ast.uid
+            where ah.block_uid <= $1
+                and ah.asset_id = (select uid from unique_assets where asset_id = $2)
+            order by ah.block_uid desc, ah.uid desc
+            limit 1";
+
+    let conn = conn!(db);
+
+    let rows = conn
+        .query(sql, &[&uid, &asset_id])
+        .await
+        .map_err(|err| AppError::DbError(err.to_string()))?;
+
+    if rows.len() < 1 {
+        return Ok(None);
+    }
+
+    let script: Option<Vec<u8>> = rows[0].get(5);
+
+    let ret = AssetItem {
+        asset_id: rows[0].get(0),
+        name: rows[0].get(1),
+        decimals: rows[0].get(2),
+        quantity: rows[0].get(3),
+        reissuable: rows[0].get(4),
+        script: script.map(|s| base64::encode(s)),
+        block_height: rows[0].get(6),
+        block_timestamp: rows[0].get(7),
     };
 
     Ok(Some(ret))
 }
 
+// only rows which changed quantity, newest first; after is (block_uid, uid)
+pub async fn asset_supply_history(
+    db: &PooledDb,
+    asset_id: &String,
+    after: Option<(i64, i64)>,
+) -> Result<(Vec<AssetSupplyItem>, bool, Option<String>), AppError> {
+    let sql = "select h.uid, h.block_uid, h.quantity, h.delta, h.tx_id, h.block_height, h.block_timestamp
+            from (
+                select ah.uid, ah.block_uid, ah.quantity, ah.tx_id,
+                    ah.quantity - coalesce(lag(ah.quantity) over (order by ah.block_uid, ah.uid), 0) delta,
+                    bm.height block_height,
+                    to_timestamp(bm.time_stamp/1000) block_timestamp
+                from asset_history ah
+                    inner join blocks_microblocks bm on ah.block_uid = bm.uid
+                where ah.asset_id = (select uid from unique_assets where asset_id = $1)
+            ) h
+            where h.delta <> 0
+                and (h.block_uid, h.uid) < ($2, $3)
+            order by h.block_uid desc, h.uid desc
+            limit $4";
+
+    let (after_block_uid, after_uid) =
+        after.unwrap_or((super::PG_MAX_BIGINT, super::PG_MAX_BIGINT));
+
+    let conn = conn!(db);
+    let params: Vec<&(dyn ToSql + Sync)> = vec![
+        &asset_id,
+        &after_block_uid,
+        &after_uid,
+        &(DEFAULT_LIMIT + 1),
+    ];
+
+    let mut rows: Vec<AssetSupplyItem> = conn
+        .query(sql, &params)
+        .await
+        .map_err(|err| AppError::DbError(err.to_string()))?
+        .iter()
+        .map(|r| AssetSupplyItem {
+            uid: r.get(0),
+            block_uid: r.get(1),
+            quantity: r.get(2),
+            delta: r.get(3),
+            tx_id: r.get(4),
+            block_height: r.get(5),
+            block_timestamp: r.get(6),
+        })
+        .collect();
+
+    let has_next_page = rows.len() > DEFAULT_LIMIT as usize;
+    if has_next_page {
+        rows.pop();
+    }
+
+    let last_cursor = rows.last().map(|r| format!("{}_{}", r.block_uid, r.uid));
+
+    Ok((rows, has_next_page, last_cursor))
+}
+
+const DATA_ENTRY_COLUMNS: &str = "deh.uid, deh.block_uid, ua.address, deh.key, deh.int_val, deh.bool_val, deh.bin_val, deh.str_val, deh.tx_id, bm.height block_height, to_timestamp(bm.time_stamp/1000) block_timestamp";
+
+fn data_entry_item(r: &tokio_postgres::Row) -> DataEntryItem {
+    let int_val: Option<i64> = r.get(4);
+    let bool_val: Option<bool> = r.get(5);
+    let bin_val: Option<Vec<u8>> = r.get(6);
+    let str_val: Option<String> = r.get(7);
+
+    let value = match (int_val, bool_val, bin_val, str_val) {
+        (Some(i), _, _, _) => Some(DataEntryValue::Integer(i)),
+        (_, Some(b), _, _) => Some(DataEntryValue::Boolean(b)),
+        (_, _, Some(b), _) => Some(DataEntryValue::Binary(format!(
+            "base64:{}",
+            base64::encode(b)
+        ))),
+        (_, _, _, Some(s)) => Some(DataEntryValue::String(s)),
+        _ => None,
+    };
+
+    DataEntryItem {
+        uid: r.get(0),
+        block_uid: r.get(1),
+        address: r.get(2),
+        key: r.get(3),
+        value: value,
+        tx_id: r.get(8),
+        block_height: r.get(9),
+        block_timestamp: r.get(10),
+    }
+}
+
+// None if the key was never set or is deleted at the given uid
+pub async fn data_entry_query(
+    db: &PooledDb,
+    uid: &i64,
+    address: &String,
+    key: &String,
+) -> Result<Option<DataEntryItem>, AppError> {
+    let sql = format!(
+        "select {DATA_ENTRY_COLUMNS}
+            from data_entries_history deh
+                inner join blocks_microblocks bm on deh.block_uid = bm.uid
+                inner join unique_address ua on deh.address_id = ua.uid
+            where deh.block_uid <= $1
+                and deh.address_id = (select uid from unique_address where address = $2)
+                and deh.key = $3
+            order by deh.block_uid desc, deh.uid desc
+            limit 1"
+    );
+
+    let conn = conn!(db);
+
+    let item = conn
+        .query(sql.as_str(), &[&uid, &address, &key])
+        .await
+        .map_err(|err| AppError::DbError(err.to_string()))?
+        .iter()
+        .map(data_entry_item)
+        .next();
+
+    Ok(item.filter(|i| i.value.is_some()))
+}
+
+// all changes of the key including deletions, newest first; after is (block_uid, uid)
+pub async fn data_entry_history(
+    db: &PooledDb,
+    address: &String,
+    key: &String,
+    after: Option<(i64, i64)>,
+) -> Result<(Vec<DataEntryItem>, bool, Option<String>), AppError> {
+    let sql = format!(
+        "select {DATA_ENTRY_COLUMNS}
+            from data_entries_history deh
+                inner join blocks_microblocks bm on deh.block_uid = bm.uid
+                inner join unique_address ua on deh.address_id = ua.uid
+            where deh.address_id = (select uid from unique_address where address = $1)
+                and deh.key = $2
+                and (deh.block_uid, deh.uid) < ($3, $4)
+            order by deh.block_uid desc, deh.uid desc
+            limit $5"
+    );
+
+    let (after_block_uid, after_uid) =
+        after.unwrap_or((super::PG_MAX_BIGINT, super::PG_MAX_BIGINT));
+
+    let conn = conn!(db);
+    let params: Vec<&(dyn ToSql + Sync)> = vec![
+        &address,
+        &key,
+        &after_block_uid,
+        &after_uid,
+        &(DEFAULT_LIMIT + 1),
+    ];
+
+    let mut rows: Vec<DataEntryItem> = conn
+        .query(sql.as_str(), &params)
+        .await
+        .map_err(|err| AppError::DbError(err.to_string()))?
+        .iter()
+        .map(data_entry_item)
+        .collect();
+
+    let has_next_page = rows.len() > DEFAULT_LIMIT as usize;
+    if has_next_page {
+        rows.pop();
+    }
+
+    let last_cursor = rows.last().map(|r| format!("{}_{}", r.block_uid, r.uid));
+
+    Ok((rows, has_next_page, last_cursor))
+}
+
+// newest first; after is uid of the last rollback of the previous page
+pub async fn rollbacks(
+    db: &PooledDb,
+    after: Option<i64>,
+) -> Result<(Vec<RollbackItem>, bool, Option<String>), AppError> {
+    let sql = "select br.uid, br.id, br.max_height, coalesce(array_length(string_to_array(br.deleted_blocks_data, E'\\n'), 1), 0) deleted_blocks,
+                (select count(*) from balance_history_rollbacks bhr where bhr.rollback_uid = br.uid) balance_changes, br.created_at
+            from blocks_rollbacks br
+            where br.uid < $1
+            order by br.uid desc
+            limit $2";
+
+    let after = after.unwrap_or(super::PG_MAX_BIGINT);
+
+    let conn = conn!(db);
+    let mut rows: Vec<RollbackItem> = conn
+        .query(sql, &[&after, &(DEFAULT_LIMIT + 1)])
+        .await
+        .map_err(|err| AppError::DbError(err.to_string()))?
+        .iter()
+        .map(|r| RollbackItem {
+            uid: r.get(0),
+            block_id: r.get(1),
+            max_height: r.get(2),
+            deleted_blocks: r.get(3),
+            balance_changes: r.get(4),
+            created_at: r.get(5),
+        })
+        .collect();
+
+    let has_next_page = rows.len() > DEFAULT_LIMIT as usize;
+    if has_next_page {
+        rows.pop();
+    }
+
+    let last_cursor = rows.last().map(|r| r.uid.to_string());
+
+    Ok((rows, has_next_page, last_cursor))
+}
+
+// in the order of the original balance_history rows
+pub async fn rolled_back_balance_changes(
+    db: &PooledDb,
+    rollback_uid: i64,
+    after: Option<i64>,
+) -> Result<(Vec<RolledBackBalanceItem>, bool, Option<String>), AppError> {
+    let sql = "select bhr.uid, ad.address, ast.asset_id, bhr.amount, bhr.amount_before, bhr.delta, bhr.tx_id, bhr.block_id, bhr.height
+            from balance_history_rollbacks bhr
+                inner join unique_assets ast on bhr.asset_id = ast.uid
+                inner join unique_address ad on bhr.address_id = ad.uid
+            where bhr.rollback_uid = $1
+                and bhr.uid > $2
+            order by bhr.uid
+            limit $3";
+
+    let after = after.unwrap_or(0);
+
+    let conn = conn!(db);
+    let mut rows: Vec<RolledBackBalanceItem> = conn
+        .query(sql, &[&rollback_uid, &after, &(DEFAULT_LIMIT + 1)])
+        .await
+        .map_err(|err| AppError::DbError(err.to_string()))?
+        .iter()
+        .map(|r| RolledBackBalanceItem {
+            uid: r.get(0),
+            address: r.get(1),
+            asset_id: r.get(2),
+            amount: r.get(3),
+            amount_before: r.get(4),
+            delta: r.get(5),
+            tx_id: r.get(6),
+            block_id: r.get(7),
+            block_height: r.get(8),
+        })
+        .collect();
+
+    let has_next_page = rows.len() > DEFAULT_LIMIT as usize;
+    if has_next_page {
+        rows.pop();
+    }
+
+    let last_cursor = rows.last().map(|r| r.uid.to_string());
+
+    Ok((rows, has_next_page, last_cursor))
+}
+
 pub(crate) async fn balance_history_aggregated(
     db: &PooledDb,
     address: &str,
@@ -414,9 +886,10 @@ pub(crate) async fn balance_history_aggregated(
     let sql = "WITH
         balances AS (
             select
-                row_number() over (partition by date_trunc('DAY', to_timestamp(b.time_stamp/1000)) order by h.uid desc) as is_last,
+                row_number() over (partition by date_trunc('DAY', to_timestamp(b.time_stamp/1000)) order by h.block_uid desc, h.uid desc) as is_last,
                 date_trunc('DAY', to_timestamp(b.time_stamp/1000)) date_stamp,
-                amount        
+                amount,
+                delta
             from balance_history h
             inner join blocks_microblocks b on b.uid = h.block_uid 
             where h.address_id = (select uid from unique_address where address = $3)
@@ -427,16 +900,22 @@ pub(crate) async fn balance_history_aggregated(
         last_day_balances AS (
             select 
                 date_stamp,
-                (array_agg(amount))[1] last_balance
+                (array_agg(amount) filter (where is_last = 1))[1] last_balance,
+                -- sums are partial if any delta of the day is null
+                case when bool_and(delta is not null) then sum(delta) end delta,
+                case when bool_and(delta is not null) then coalesce(sum(delta) filter (where delta > 0), 0) end inflow,
+                case when bool_and(delta is not null) then coalesce(sum(-delta) filter (where delta < 0), 0) end outflow
                 from balances 
-                where is_last = 1
                 group by date_stamp
                 order by date_stamp asc
         )
         select 
             date_stamp, 
             coalesce(lag(last_balance) over (), 0) first_balance, 
-            last_balance 
+            last_balance,
+            delta,
+            inflow,
+            outflow
         from last_day_balances";
 
     let params: Vec<&(dyn ToSql + Sync)> = vec![&min_uid, &max_uid, &address, &asset_id];
@@ -453,6 +932,9 @@ pub(crate) async fn balance_history_aggregated(
             amount_begin: row.get(1),
             amount_end: row.get(2),
             date_stamp: row.get(0),
+            delta: row.get(3),
+            inflow: row.get(4),
+            outflow: row.get(5),
         };
 
         out.push(item);
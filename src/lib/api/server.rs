@@ -1,8 +1,9 @@
 use super::error::AppError;
 use super::repo::AssetDistribution;
 use super::{
-    api_custom_reject, repo, AssetDistributionItem, BalanceQuery, BalanceResponseAggItem,
-    BalanceResponseItem, SETTINGS,
+    api_custom_reject, repo, AddressesQuery, AssetDistributionItem, AssetItem, AssetSupplyItem,
+    BalanceChangeItem, BalanceQuery, BalanceResponseAggItem, BalanceResponseItem, DataEntryItem,
+    LeasingResponseItem, RollbackItem, RolledBackBalanceItem, WavesBalanceItem, SETTINGS,
 };
 use chrono::{DateTime, Timelike, Utc};
 use deadpool_postgres::Pool;
@@ -47,6 +48,13 @@ pub async fn run(rdb: Pool) -> Result<(), AppError> {
         .and_then(bh_handler_address)
         .map(|l| warp::reply::json(&l));
 
+    let bh_address_changes = warp::path!("balance_history" / "address" / String / "changes")
+        .and(warp::get())
+        .and(with_resource(rdb.clone()))
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(bh_handler_address_changes)
+        .map(|l| warp::reply::json(&l));
+
     let bh_balance_aggregates = warp::path!("balance_history" / "aggregates" / String / String)
         .and(warp::get())
         .and(warp::path::end())
@@ -56,6 +64,78 @@ pub async fn run(rdb: Pool) -> Result<(), AppError> {
         .and_then(bh_balance_aggregates)
         .map(|l| warp::reply::json(&l));
 
+    let lh = warp::path!("leasing_history")
+        .and(warp::post())
+        .and(with_resource(rdb.clone()))
+        .and(warp::body::json::<AddressesQuery>())
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(lh_handler_by_addresses)
+        .map(|l| warp::reply::json(&l));
+
+    let lh_address = warp::path!("leasing_history" / "address" / String)
+        .and(warp::get())
+        .and(with_resource(rdb.clone()))
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(lh_handler_address)
+        .map(|l| warp::reply::json(&l));
+
+    let waves_balance = warp::path!("waves_balance")
+        .and(warp::post())
+        .and(with_resource(rdb.clone()))
+        .and(warp::body::json::<AddressesQuery>())
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(waves_balance_handler_by_addresses)
+        .map(|l| warp::reply::json(&l));
+
+    let waves_balance_address = warp::path!("waves_balance" / "address" / String)
+        .and(warp::get())
+        .and(with_resource(rdb.clone()))
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(waves_balance_handler_address)
+        .map(|l| warp::reply::json(&l));
+
+    let asset = warp::path!("assets" / String)
+        .and(warp::get())
+        .and(with_resource(rdb.clone()))
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(asset_handler)
+        .map(|a| warp::reply::json(&a));
+
+    let asset_supply_history = warp::path!("assets" / String / "supply_history")
+        .and(warp::get())
+        .and(with_resource(rdb.clone()))
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(asset_supply_history_handler)
+        .map(|l| warp::reply::json(&l));
+
+    let data_entry = warp::path!("data_entries" / String)
+        .and(warp::get())
+        .and(with_resource(rdb.clone()))
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(data_entry_handler)
+        .map(|d| warp::reply::json(&d));
+
+    let data_entry_history = warp::path!("data_entries" / String / "history")
+        .and(warp::get())
+        .and(with_resource(rdb.clone()))
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(data_entry_history_handler)
+        .map(|l| warp::reply::json(&l));
+
+    let rollbacks = warp::path!("rollbacks")
+        .and(warp::get())
+        .and(with_resource(rdb.clone()))
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(rollbacks_handler)
+        .map(|l| warp::reply::json(&l));
+
+    let rollback_balance_changes = warp::path!("rollbacks" / i64 / "balance_changes")
+        .and(warp::get())
+        .and(with_resource(rdb.clone()))
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(rollback_balance_changes_handler)
+        .map(|l| warp::reply::json(&l));
+
     let bh_asset_distribution = warp::path!("asset_distribution" / String / u32)
         .and(warp::get())
         .and(with_resource(rdb.clone()))
@@ -100,9 +180,21 @@ pub async fn run(rdb: Pool) -> Result<(), AppError> {
 
     let routes = bh
         .or(bh_address)
+        .or(bh_address_changes)
         .or(bh_balance_aggregates)
+        .or(lh)
+        .or(lh_address)
+        .or(waves_balance)
+        .or(waves_balance_address)
+        .or(asset)
+        .or(asset_supply_history)
+        .or(data_entry)
+        .or(data_entry_history)
+        .or(rollbacks)
+        .or(rollback_balance_changes)
         .or(bh_asset_distribution)
         .or(bh_asset_distribution_task)
+        .recover(not_found_handler)
         .recover(move |rej| {
             error_handler_with_serde_qs(ERROR_CODES_PREFIX, error_handler.clone())(rej)
         })
@@ -120,6 +212,21 @@ pub async fn run(rdb: Pool) -> Result<(), AppError> {
     Ok(())
 }
 
+// the common error handler has no response for missing resources
+async fn not_found_handler(rej: reject::Rejection) -> Result<impl warp::Reply, reject::Rejection> {
+    match rej.find::<AppError>() {
+        Some(err @ AppError::NotFound(_)) => {
+            let body = serde_json::json!({ "message": err.to_string() });
+
+            Ok(warp::reply::with_status(
+                warp::reply::json(&body),
+                warp::http::StatusCode::NOT_FOUND,
+            ))
+        }
+        _ => Err(rej),
+    }
+}
+
 async fn bh_handler_by_pairs(
     rdb: Pool,
     req: BalanceQuery,
@@ -168,6 +275,178 @@ async fn bh_handler_address(
     Ok(list)
 }
 
+async fn bh_handler_address_changes(
+    address: String,
+    rdb: Pool,
+    get_params: HashMap<String, String>,
+) -> Result<List<BalanceChangeItem>, reject::Rejection> {
+    let uid = repo::get_uids_from_req(&rdb, &get_params).await?;
+    let after = parse_after_cursor(&get_params)?;
+
+    let (items, has_next_page, last_cursor) = repo::balance_changes_by_address(
+        &rdb,
+        &address,
+        get_params.get("asset_id".into()),
+        &uid,
+        after,
+    )
+    .await?;
+
+    let list = List {
+        items: items,
+        page_info: PageInfo {
+            last_cursor: last_cursor,
+            has_next_page: has_next_page,
+        },
+    };
+
+    Ok(list)
+}
+
+async fn asset_handler(
+    asset_id: String,
+    rdb: Pool,
+    get_params: HashMap<String, String>,
+) -> Result<AssetItem, reject::Rejection> {
+    let uid = repo::get_uids_from_req(&rdb, &get_params).await?;
+
+    repo::asset_query(&rdb, &uid, &asset_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("asset {} not found", asset_id)).into())
+}
+
+async fn asset_supply_history_handler(
+    asset_id: String,
+    rdb: Pool,
+    get_params: HashMap<String, String>,
+) -> Result<List<AssetSupplyItem>, reject::Rejection> {
+    let after = parse_after_cursor(&get_params)?;
+
+    let (items, has_next_page, last_cursor) =
+        repo::asset_supply_history(&rdb, &asset_id, after).await?;
+
+    let list = List {
+        items: items,
+        page_info: PageInfo {
+            last_cursor: last_cursor,
+            has_next_page: has_next_page,
+        },
+    };
+
+    Ok(list)
+}
+
+async fn data_entry_handler(
+    address: String,
+    rdb: Pool,
+    get_params: HashMap<String, String>,
+) -> Result<DataEntryItem, reject::Rejection> {
+    let key = data_entry_key(&get_params)?;
+    let uid = repo::get_uids_from_req(&rdb, &get_params).await?;
+
+    repo::data_entry_query(&rdb, &uid, &address, key)
+        .await?
+        .ok_or_else(|| {
+            AppError::NotFound(format!("data entry {} of {} not found", key, address)).into()
+        })
+}
+
+async fn data_entry_history_handler(
+    address: String,
+    rdb: Pool,
+    get_params: HashMap<String, String>,
+) -> Result<List<DataEntryItem>, reject::Rejection> {
+    let key = data_entry_key(&get_params)?;
+    let after = parse_after_cursor(&get_params)?;
+
+    let (items, has_next_page, last_cursor) =
+        repo::data_entry_history(&rdb, &address, key, after).await?;
+
+    let list = List {
+        items: items,
+        page_info: PageInfo {
+            last_cursor: last_cursor,
+            has_next_page: has_next_page,
+        },
+    };
+
+    Ok(list)
+}
+
+async fn rollbacks_handler(
+    rdb: Pool,
+    get_params: HashMap<String, String>,
+) -> Result<List<RollbackItem>, reject::Rejection> {
+    let after = parse_after_uid(&get_params)?;
+
+    let (items, has_next_page, last_cursor) = repo::rollbacks(&rdb, after).await?;
+
+    let list = List {
+        items: items,
+        page_info: PageInfo {
+            last_cursor: last_cursor,
+            has_next_page: has_next_page,
+        },
+    };
+
+    Ok(list)
+}
+
+async fn rollback_balance_changes_handler(
+    rollback_uid: i64,
+    rdb: Pool,
+    get_params: HashMap<String, String>,
+) -> Result<List<RolledBackBalanceItem>, reject::Rejection> {
+    let after = parse_after_uid(&get_params)?;
+
+    let (items, has_next_page, last_cursor) =
+        repo::rolled_back_balance_changes(&rdb, rollback_uid, after).await?;
+
+    let list = List {
+        items: items,
+        page_info: PageInfo {
+            last_cursor: last_cursor,
+            has_next_page: has_next_page,
+        },
+    };
+
+    Ok(list)
+}
+
+// keys may contain any characters, so the key is passed in the query string
+fn data_entry_key(get_params: &HashMap<String, String>) -> Result<&String, AppError> {
+    get_params
+        .get("key".into())
+        .ok_or_else(|| AppError::InvalidQueryString("key is required".into()))
+}
+
+// cursor format: {block_uid}_{uid}
+fn parse_after_cursor(
+    get_params: &HashMap<String, String>,
+) -> Result<Option<(i64, i64)>, AppError> {
+    match get_params.get("after".into()) {
+        Some(a) => match a.split_once('_') {
+            Some((block_uid, uid)) => match (block_uid.parse::<i64>(), uid.parse::<i64>()) {
+                (Ok(block_uid), Ok(uid)) => Ok(Some((block_uid, uid))),
+                _ => Err(AppError::InvalidQueryString("invalid after".into())),
+            },
+            None => Err(AppError::InvalidQueryString("invalid after".into())),
+        },
+        None => Ok(None),
+    }
+}
+
+// cursor format: {uid}
+fn parse_after_uid(get_params: &HashMap<String, String>) -> Result<Option<i64>, AppError> {
+    match get_params.get("after".into()) {
+        Some(a) => a
+            .parse::<i64>()
+            .map(Some)
+            .map_err(|_| AppError::InvalidQueryString("invalid after".into())),
+        None => Ok(None),
+    }
+}
+
 async fn bh_balance_aggregates(
     address: String,
     asset_id: String,
@@ -226,6 +505,108 @@ async fn bh_balance_aggregates(
     Ok(list)
 }
 
+async fn lh_handler_by_addresses(
+    rdb: Pool,
+    req: AddressesQuery,
+    get_params: HashMap<String, String>,
+) -> Result<List<LeasingResponseItem>, reject::Rejection> {
+    let uid = repo::get_uids_from_req(&rdb, &get_params).await?;
+
+    if req.addresses.len() > BALANCE_HISTORY_PAIRS_LIMIT as usize {
+        return Err(AppError::ValidationErrorCustom(format!(
+            "addresses limited to {}",
+            BALANCE_HISTORY_PAIRS_LIMIT
+        ))
+        .into());
+    }
+
+    let items = repo::get_leasing_by_addresses(&rdb, &uid, &req).await?;
+
+    let list = List {
+        items: items,
+        page_info: PageInfo {
+            last_cursor: None,
+            has_next_page: false,
+        },
+    };
+
+    Ok(list)
+}
+
+async fn lh_handler_address(
+    address: String,
+    rdb: Pool,
+    get_params: HashMap<String, String>,
+) -> Result<List<LeasingResponseItem>, reject::Rejection> {
+    let uid = repo::get_uids_from_req(&rdb, &get_params).await?;
+
+    let items: Vec<LeasingResponseItem> = repo::leasing_query(&rdb, &uid, &address)
+        .await
+        .map_err(|err| AppError::DbError(err.to_string()))?
+        .into_iter()
+        .collect();
+
+    let list = List {
+        items: items,
+        page_info: PageInfo {
+            last_cursor: None,
+            has_next_page: false,
+        },
+    };
+
+    Ok(list)
+}
+
+async fn waves_balance_handler_by_addresses(
+    rdb: Pool,
+    req: AddressesQuery,
+    get_params: HashMap<String, String>,
+) -> Result<List<WavesBalanceItem>, reject::Rejection> {
+    let uid = repo::get_uids_from_req(&rdb, &get_params).await?;
+
+    if req.addresses.len() > BALANCE_HISTORY_PAIRS_LIMIT as usize {
+        return Err(AppError::ValidationErrorCustom(format!(
+            "addresses limited to {}",
+            BALANCE_HISTORY_PAIRS_LIMIT
+        ))
+        .into());
+    }
+
+    let items = repo::get_waves_balances_by_addresses(&rdb, &uid, &req).await?;
+
+    let list = List {
+        items: items,
+        page_info: PageInfo {
+            last_cursor: None,
+            has_next_page: false,
+        },
+    };
+
+    Ok(list)
+}
+
+async fn waves_balance_handler_address(
+    address: String,
+    rdb: Pool,
+    get_params: HashMap<String, String>,
+) -> Result<List<WavesBalanceItem>, reject::Rejection> {
+    let uid = repo::get_uids_from_req(&rdb, &get_params).await?;
+
+    let item = repo::waves_balance_query(&rdb, &uid, &address)
+        .await
+        .map_err(|err| AppError::DbError(err.to_string()))?;
+
+    let list = List {
+        items: vec![item],
+        page_info: PageInfo {
+            last_cursor: None,
+            has_next_page: false,
+        },
+    };
+
+    Ok(list)
+}
+
 async fn bh_handler_asset_distribution(
     asset_id: String,
     height: u32,
@@ -2,6 +2,38 @@ use crate::config::postgres::PostgresConfig;
 use anyhow::Result;
 use serde::Deserialize;
 
+fn default_flush_max_rows() -> usize {
+    1000
+}
+
+fn default_flush_max_latency_ms() -> u64 {
+    1000
+}
+
+fn default_lock_wait() -> bool {
+    true
+}
+
+fn default_metrics_port() -> u16 {
+    9090
+}
+
+fn default_backfill_workers() -> usize {
+    4
+}
+
+fn default_backfill_segment_size() -> i32 {
+    10000
+}
+
+fn default_blocks_batch_size() -> usize {
+    100
+}
+
+fn default_id_cache_size() -> usize {
+    100000
+}
+
 #[derive(Deserialize, Debug, Clone)]
 struct ConfigFlat {
     pub pghost: String,
@@ -13,15 +45,93 @@ struct ConfigFlat {
     pub pgkeepalives_idle: u32,
     // #[serde(default = "default_pgpool")]
     // pub pgpoolsize: u32,
+    #[serde(default)]
     pub blockchain_updates_url: String,
+    pub blockchain_updates_file: Option<String>,
     pub blockchain_start_height: i32,
+    pub blockchain_to_height: Option<i32>,
+    pub blockchain_to_timestamp: Option<i64>,
+    #[serde(default)]
+    pub ingest_include_assets: Vec<String>,
+    #[serde(default)]
+    pub ingest_exclude_assets: Vec<String>,
+    #[serde(default)]
+    pub ingest_include_addresses: Vec<String>,
+    #[serde(default)]
+    pub ingest_exclude_addresses: Vec<String>,
+    pub backfill_to_height: Option<i32>,
+    #[serde(default = "default_backfill_workers")]
+    pub backfill_workers: usize,
+    #[serde(default = "default_backfill_segment_size")]
+    pub backfill_segment_size: i32,
+    #[serde(default = "default_id_cache_size")]
+    pub id_cache_size: usize,
+    #[serde(default = "default_blocks_batch_size")]
+    pub blocks_batch_size: usize,
+    #[serde(default = "default_metrics_port")]
+    pub metrics_port: u16,
+    #[serde(default = "default_lock_wait")]
+    pub lock_wait: bool,
+    #[serde(default)]
+    pub solidified_only: bool,
+    #[serde(default = "default_flush_max_rows")]
+    pub flush_max_rows: usize,
+    #[serde(default)]
+    pub flush_max_blocks: usize,
+    #[serde(default = "default_flush_max_latency_ms")]
+    pub flush_max_latency_ms: u64,
+}
+
+// comma separated lists, empty include list means everything is included
+#[derive(Debug, Clone, Default)]
+pub struct IngestFilterConfig {
+    pub include_assets: Vec<String>,
+    pub exclude_assets: Vec<String>,
+    pub include_addresses: Vec<String>,
+    pub exclude_addresses: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BackfillConfig {
+    // heights up to this one are loaded by parallel workers before the live consumer starts,
+    // must be deep enough to never be rolled back
+    pub to_height: Option<i32>,
+    pub workers: usize,
+    pub segment_size: i32,
+}
+
+// chunk analyzers save collected rows when any of the limits is reached
+#[derive(Debug, Clone)]
+pub struct FlushConfig {
+    pub max_rows: usize,
+    // 0 - no limit
+    pub max_blocks: usize,
+    // since the first not saved block, 0 saves every block
+    pub max_latency_ms: u64,
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub blockchain_updates_url: String,
+    // replay length-delimited SubscribeEvent's from file instead of grpc
+    pub blockchain_updates_file: Option<String>,
     pub blockchain_start_height: i32,
+    // bounded ingestion: stop after this height or before the first block with greater timestamp (ms)
+    pub blockchain_to_height: Option<i32>,
+    pub blockchain_to_timestamp: Option<i64>,
     pub postgres: PostgresConfig,
+    pub ingest_filter: IngestFilterConfig,
+    pub backfill: BackfillConfig,
+    // max cached uids of addresses and of assets per analyzer
+    pub id_cache_size: usize,
+    // blocks committed in one transaction during catch-up, 1 commits every block
+    pub blocks_batch_size: usize,
+    pub metrics_port: u16,
+    // wait for another instance to release the single writer lock instead of exiting
+    pub lock_wait: bool,
+    // save only blocks confirmed by the next block, microblocks are merged in memory
+    pub solidified_only: bool,
+    pub flush: FlushConfig,
     pub test_changed: Vec<String>,
 }
 
@@ -30,7 +140,10 @@ pub fn load() -> Result<Config> {
 
     Ok(Config {
         blockchain_updates_url: config_flat.blockchain_updates_url,
+        blockchain_updates_file: config_flat.blockchain_updates_file,
         blockchain_start_height: config_flat.blockchain_start_height,
+        blockchain_to_height: config_flat.blockchain_to_height,
+        blockchain_to_timestamp: config_flat.blockchain_to_timestamp,
         postgres: PostgresConfig {
             host: config_flat.pghost,
             port: config_flat.pgport,
@@ -41,6 +154,27 @@ pub fn load() -> Result<Config> {
             connection_timeout: config_flat.pgconnection_timeout,
             keepalives_idle: config_flat.pgkeepalives_idle,
         },
+        ingest_filter: IngestFilterConfig {
+            include_assets: config_flat.ingest_include_assets,
+            exclude_assets: config_flat.ingest_exclude_assets,
+            include_addresses: config_flat.ingest_include_addresses,
+            exclude_addresses: config_flat.ingest_exclude_addresses,
+        },
+        backfill: BackfillConfig {
+            to_height: config_flat.backfill_to_height,
+            workers: config_flat.backfill_workers,
+            segment_size: config_flat.backfill_segment_size,
+        },
+        id_cache_size: config_flat.id_cache_size,
+        blocks_batch_size: config_flat.blocks_batch_size,
+        metrics_port: config_flat.metrics_port,
+        lock_wait: config_flat.lock_wait,
+        solidified_only: config_flat.solidified_only,
+        flush: FlushConfig {
+            max_rows: config_flat.flush_max_rows,
+            max_blocks: config_flat.flush_max_blocks,
+            max_latency_ms: config_flat.flush_max_latency_ms,
+        },
         test_changed: vec![],
     })
 }
@@ -1,3 +1,4 @@
 pub mod consumer;
 pub mod migration;
 pub mod postgres;
+pub mod verify;
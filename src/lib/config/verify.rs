@@ -0,0 +1,53 @@
+use crate::config::postgres::PostgresConfig;
+use anyhow::Result;
+use serde::Deserialize;
+
+fn default_verify_sample_size() -> u32 {
+    100
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ConfigFlat {
+    pub pghost: String,
+    pub pgport: u16,
+    pub pgdatabase: String,
+    pub pguser: String,
+    pub pgpassword: String,
+    pub pgconnection_timeout: u32,
+    pub pgkeepalives_idle: u32,
+    pub node_url: String,
+    #[serde(default = "default_verify_sample_size")]
+    pub verify_sample_size: u32,
+    pub verify_height: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // base url of a node (or a stub) serving POST /addresses/balance
+    pub node_url: String,
+    // address/asset pairs sampled from balance_history
+    pub sample_size: u32,
+    // last solidified height if not set
+    pub height: Option<u32>,
+    pub postgres: PostgresConfig,
+}
+
+pub fn load() -> Result<Config> {
+    let config_flat = envy::from_env::<ConfigFlat>()?;
+
+    Ok(Config {
+        node_url: config_flat.node_url,
+        sample_size: config_flat.verify_sample_size,
+        height: config_flat.verify_height,
+        postgres: PostgresConfig {
+            host: config_flat.pghost,
+            port: config_flat.pgport,
+            database: config_flat.pgdatabase,
+            user: config_flat.pguser,
+            password: config_flat.pgpassword,
+            pool_size: 4,
+            connection_timeout: config_flat.pgconnection_timeout,
+            keepalives_idle: config_flat.pgkeepalives_idle,
+        },
+    })
+}
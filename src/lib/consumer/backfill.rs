@@ -0,0 +1,304 @@
+use super::{
+    backoff::Backoff,
+    event_source::{self, EventSource},
+    new_chunks, new_event_source, shutdown, GRPC_STREAM_AWAIT_TIMEOUT_SECS,
+    RECONNECT_BACKOFF_INITIAL_MS, RECONNECT_BACKOFF_MAX_SECS, SETTINGS,
+};
+use crate::db::{
+    mappers::{blocks_microblocks, safe_heights},
+    *,
+};
+use crate::waves::{
+    bu::{chunk_analyzer::Chunk, filters::IngestFilter},
+    BlockType, BlockchainUpdateInfo,
+};
+use anyhow::{anyhow, Result};
+use futures::{stream, StreamExt};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::{select, sync::watch};
+use wavesexchange_log::{info, warn};
+
+// blocks and rows committed by a worker in one transaction
+const BATCH_MAX_BLOCKS: usize = 100;
+const BATCH_MAX_ROWS: usize = 5000;
+// attempts without progress before the segment fails
+const SEGMENT_RETRY_MAX_ATTEMPTS: u32 = 10;
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    from: i32,
+    to: i32,
+}
+
+// Loads [from_height, to_height] by segments in parallel and returns the height live consumer continues from.
+// Blocks get uid = uid_offset + height, so uids follow heights whatever order segments are saved in.
+// Safe heights only move over the loaded prefix, so after a crash init_db_data drops partially loaded segments.
+// On shutdown workers stop between batches and None is returned.
+pub async fn run(
+    from_height: i32,
+    to_height: i32,
+    filter: &IngestFilter,
+    shutdown: watch::Receiver<bool>,
+) -> Result<Option<i32>> {
+    let config = &SETTINGS.config.backfill;
+
+    let mut db = Db::new(&SETTINGS.config.postgres).await?;
+
+    let uid_offset = blocks_microblocks::get_max_uid(&db).await? - from_height as i64 + 1;
+
+    for chunk in new_chunks(filter, false) {
+        safe_heights::init(&db, chunk.safe_heights_table(), from_height - 1).await?;
+    }
+
+    let segment_size = std::cmp::max(config.segment_size, 1);
+    let segments: Vec<Segment> = (from_height..=to_height)
+        .step_by(segment_size as usize)
+        .map(|from| Segment {
+            from: from,
+            to: std::cmp::min(from + segment_size - 1, to_height),
+        })
+        .collect();
+
+    info!(
+        "backfill heights {}..={} by {} segments with {} workers",
+        from_height,
+        to_height,
+        segments.len(),
+        config.workers
+    );
+
+    let mut loaded = stream::iter(segments)
+        .map(|segment| {
+            let filter = filter.clone();
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move { load_segment(segment, uid_offset, filter, shutdown).await })
+        })
+        .buffer_unordered(std::cmp::max(config.workers, 1));
+
+    // loaded segments by from height, waiting for the prefix to reach them
+    let mut completed: BTreeMap<i32, i32> = BTreeMap::new();
+    let mut next_height = from_height;
+    let mut stopped = false;
+
+    // workers started after shutdown stop at once, all of them are awaited
+    while let Some(res) = loaded.next().await {
+        let segment = match res.map_err(|err| anyhow!("backfill worker panic: {}", err))?? {
+            Some(segment) => segment,
+            None => {
+                stopped = true;
+                continue;
+            }
+        };
+
+        completed.insert(segment.from, segment.to);
+
+        let prev_height = next_height;
+        while let Some(to) = completed.remove(&next_height) {
+            next_height = to + 1;
+        }
+
+        if next_height > prev_height {
+            safe_heights::set_all(&db, next_height - 1).await?;
+            info!("backfill: loaded all heights up to {}", next_height - 1);
+        }
+    }
+
+    if stopped {
+        info!(
+            "backfill stopped, all heights up to {} are loaded",
+            next_height - 1
+        );
+        return Ok(None);
+    }
+
+    let tr = db.transaction().await?;
+    blocks_microblocks::reset_uid_sequence(&tr).await?;
+    tr.commit().await?;
+
+    info!("backfill finished at height {}", to_height);
+
+    Ok(Some(to_height + 1))
+}
+
+// None when stopped on shutdown
+async fn load_segment(
+    segment: Segment,
+    uid_offset: i64,
+    filter: IngestFilter,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<Option<Segment>> {
+    let mut db = Db::new(&SETTINGS.config.postgres).await?;
+    let mut source = new_event_source();
+    let mut chunks = new_chunks(&filter, false);
+
+    let mut backoff = Backoff::new(
+        Duration::from_millis(RECONNECT_BACKOFF_INITIAL_MS),
+        Duration::from_secs(RECONNECT_BACKOFF_MAX_SECS),
+    );
+
+    let mut from_height = segment.from;
+    let mut attempt = 1;
+
+    loop {
+        let attempt_from = from_height;
+
+        let err = match load_range(
+            source.as_mut(),
+            &mut db,
+            &mut chunks,
+            &mut from_height,
+            segment.to,
+            uid_offset,
+            &mut shutdown,
+        )
+        .await
+        {
+            Ok(true) => return Ok(Some(segment)),
+            Ok(false) => return Ok(None),
+            Err(err) => err,
+        };
+
+        // rows of the failed batch are not committed
+        chunks.iter_mut().for_each(|c| c.clear());
+
+        if from_height > attempt_from {
+            attempt = 1;
+            backoff.reset();
+        }
+
+        if attempt >= SEGMENT_RETRY_MAX_ATTEMPTS || !event_source::is_transient(&err) {
+            return Err(err.context(format!(
+                "backfill of heights {}..={} failed at height {}",
+                segment.from, segment.to, from_height
+            )));
+        }
+
+        let delay = backoff.next_delay();
+        warn!(
+            "backfill of heights {}..={} interrupted at height {}: {}; retry in {} ms",
+            segment.from,
+            segment.to,
+            from_height,
+            err,
+            delay.as_millis()
+        );
+
+        select! {
+            _ = tokio::time::sleep(delay) => {},
+            Ok(()) = shutdown.changed() => return Ok(None),
+        }
+
+        if db.is_closed() {
+            db = Db::new(&SETTINGS.config.postgres).await?;
+        }
+
+        attempt += 1;
+    }
+}
+
+// from_height is moved forward after every committed batch;
+// returns false when stopped on shutdown, blocks of the batch in progress are dropped
+async fn load_range(
+    source: &mut dyn EventSource,
+    db: &mut Db,
+    chunks: &mut [Box<dyn Chunk>],
+    from_height: &mut i32,
+    to_height: i32,
+    uid_offset: i64,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Result<bool> {
+    if shutdown::is_requested(shutdown) {
+        return Ok(false);
+    }
+
+    source.subscribe(*from_height, Some(to_height)).await?;
+
+    let await_timeout = Duration::from_secs(GRPC_STREAM_AWAIT_TIMEOUT_SECS);
+    let mut blocks: Vec<(i64, String, i32, i64)> = vec![];
+
+    loop {
+        let event = select! {
+            event = tokio::time::timeout(await_timeout, source.next_event()) => {
+                event.map_err(|_| anyhow!("event stream await timeout"))??
+            }
+            Ok(()) = shutdown.changed() => return Ok(false),
+        };
+
+        let mut block: BlockchainUpdateInfo = match event {
+            Some(event) => Some(event).into(),
+            None => break,
+        };
+
+        let height = block.height.unwrap() as i32;
+
+        // history deep enough consists of solidified blocks only
+        if block.block_type != BlockType::Block {
+            return Err(anyhow!(
+                "unexpected {} at height {} during backfill",
+                block.block_type,
+                height
+            ));
+        }
+
+        block.uid = Some(uid_offset + height as i64);
+
+        chunks.iter_mut().for_each(|c| c.process(&block));
+
+        blocks.push((
+            block.uid.unwrap(),
+            block.id.clone().unwrap(),
+            height,
+            block.timestamp.unwrap(),
+        ));
+
+        let rows: usize = chunks.iter().map(|c| c.len()).sum();
+        if blocks.len() >= BATCH_MAX_BLOCKS || rows >= BATCH_MAX_ROWS {
+            save_batch(db, &blocks, chunks).await?;
+            *from_height = height + 1;
+            blocks.clear();
+
+            if shutdown::is_requested(shutdown) {
+                return Ok(false);
+            }
+        }
+    }
+
+    if !blocks.is_empty() {
+        save_batch(db, &blocks, chunks).await?;
+        *from_height = blocks.last().unwrap().2 + 1;
+    }
+
+    if *from_height <= to_height {
+        return Err(anyhow!(
+            "event stream ended at height {} before {}",
+            *from_height - 1,
+            to_height
+        ));
+    }
+
+    Ok(true)
+}
+
+async fn save_batch(
+    db: &mut Db,
+    blocks: &[(i64, String, i32, i64)],
+    chunks: &mut [Box<dyn Chunk>],
+) -> Result<()> {
+    let tr = db.transaction().await?;
+
+    blocks_microblocks::save_solidified_bulk(&tr, blocks).await?;
+
+    for chunk in chunks.iter() {
+        chunk.save_rows(&tr).await?;
+    }
+
+    tr.commit().await?;
+
+    chunks.iter_mut().for_each(|c| {
+        c.saved();
+        c.clear();
+    });
+
+    Ok(())
+}
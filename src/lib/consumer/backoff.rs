@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+// exponential backoff: initial, initial * 2, ... capped by max
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            current: initial,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = std::cmp::min(self.current * 2, self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_is_capped_by_max() {
+        // 500ms doubled reaches 4s, the max is not a power of two of initial
+        let mut b = Backoff::new(Duration::from_millis(500), Duration::from_secs(3));
+
+        let delays: Vec<u128> = (0..5).map(|_| b.next_delay().as_millis()).collect();
+
+        assert_eq!(delays, vec![500, 1000, 2000, 3000, 3000]);
+    }
+
+    #[test]
+    fn reset_after_progress_starts_from_initial_delay() {
+        let mut b = Backoff::new(Duration::from_millis(500), Duration::from_secs(3));
+        b.next_delay();
+        b.next_delay();
+
+        b.reset();
+
+        assert_eq!(b.next_delay(), Duration::from_millis(500));
+        assert_eq!(b.next_delay(), Duration::from_millis(1000));
+    }
+}
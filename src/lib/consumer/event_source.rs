@@ -0,0 +1,344 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use prost::Message;
+use std::{fmt, io::ErrorKind, path::PathBuf};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, BufReader},
+};
+use tonic::Streaming;
+use waves_protobuf_schemas::waves::events::grpc::{
+    blockchain_updates_api_client::BlockchainUpdatesApiClient, SubscribeEvent, SubscribeRequest,
+};
+
+// max bytes in varint encoded u64
+const MAX_VARINT_LEN: usize = 10;
+
+// the events file is read as is, so retrying after these errors doesn't help
+#[derive(Debug, thiserror::Error)]
+#[error("events file: {0}")]
+pub struct EventsFileError(String);
+
+// stream errors are retried with resubscribe unless the source can't recover from them
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().all(|e| {
+        if e.is::<EventsFileError>() {
+            return false;
+        }
+
+        match e.downcast_ref::<tonic::Status>() {
+            Some(status) => !matches!(
+                status.code(),
+                tonic::Code::InvalidArgument
+                    | tonic::Code::Unimplemented
+                    | tonic::Code::Unauthenticated
+                    | tonic::Code::PermissionDenied
+            ),
+            None => true,
+        }
+    })
+}
+
+#[async_trait]
+pub trait EventSource: Send + fmt::Display {
+    // (re)starts the stream of events beginning with from_height,
+    // with to_height the stream is exhausted after the block of that height
+    async fn subscribe(&mut self, from_height: i32, to_height: Option<i32>) -> Result<()>;
+
+    // returns None when the stream is exhausted
+    async fn next_event(&mut self) -> Result<Option<SubscribeEvent>>;
+}
+
+pub struct GrpcSource {
+    url: String,
+    stream: Option<Streaming<SubscribeEvent>>,
+}
+
+impl GrpcSource {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            stream: None,
+        }
+    }
+}
+
+impl fmt::Display for GrpcSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "grpc {}", self.url)
+    }
+}
+
+#[async_trait]
+impl EventSource for GrpcSource {
+    async fn subscribe(&mut self, from_height: i32, to_height: Option<i32>) -> Result<()> {
+        let request = tonic::Request::new(SubscribeRequest {
+            from_height,
+            to_height: to_height.unwrap_or(0),
+        });
+
+        let stream = BlockchainUpdatesApiClient::connect(self.url.clone())
+            .await?
+            .subscribe(request)
+            .await?
+            .into_inner();
+
+        self.stream = Some(stream);
+
+        Ok(())
+    }
+
+    async fn next_event(&mut self) -> Result<Option<SubscribeEvent>> {
+        match self.stream.as_mut() {
+            Some(stream) => Ok(stream.message().await?),
+            None => Err(anyhow!("grpc source is not subscribed")),
+        }
+    }
+}
+
+// reads SubscribeEvent messages written one after another with length prefix
+// (prost::Message::encode_length_delimited)
+pub struct FileSource {
+    path: PathBuf,
+    reader: Option<BufReader<File>>,
+    from_height: i32,
+    to_height: Option<i32>,
+    started: bool,
+}
+
+impl FileSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            reader: None,
+            from_height: 0,
+            to_height: None,
+            started: false,
+        }
+    }
+}
+
+impl fmt::Display for FileSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "file {}", self.path.display())
+    }
+}
+
+#[async_trait]
+impl EventSource for FileSource {
+    async fn subscribe(&mut self, from_height: i32, to_height: Option<i32>) -> Result<()> {
+        let file = File::open(&self.path)
+            .await
+            .map_err(|e| EventsFileError(format!("can't open {}: {}", self.path.display(), e)))?;
+
+        self.reader = Some(BufReader::new(file));
+        self.from_height = from_height;
+        self.to_height = to_height;
+        self.started = false;
+
+        Ok(())
+    }
+
+    async fn next_event(&mut self) -> Result<Option<SubscribeEvent>> {
+        let reader = match self.reader.as_mut() {
+            Some(r) => r,
+            None => return Err(anyhow!("file source is not subscribed")),
+        };
+
+        while let Some(event) = read_delimited(reader).await? {
+            let height = event.update.as_ref().map(|u| u.height).unwrap_or(0);
+
+            // events recorded before the requested height are skipped,
+            // everything after the first matching one is replayed as is (rollbacks included)
+            if !self.started {
+                if height < self.from_height {
+                    continue;
+                }
+                self.started = true;
+            }
+
+            if matches!(self.to_height, Some(to) if height > to) {
+                self.reader = None;
+                return Ok(None);
+            }
+
+            return Ok(Some(event));
+        }
+
+        Ok(None)
+    }
+}
+
+async fn read_delimited<R: AsyncRead + Unpin + Send>(
+    reader: &mut R,
+) -> Result<Option<SubscribeEvent>> {
+    let len = match read_varint(reader).await? {
+        Some(len) => len,
+        None => return Ok(None),
+    };
+
+    let mut buf = vec![0u8; len as usize];
+    reader
+        .read_exact(&mut buf)
+        .await
+        .map_err(|e| EventsFileError(format!("truncated event: {}", e)))?;
+
+    let event = SubscribeEvent::decode(&buf[..])
+        .map_err(|e| EventsFileError(format!("can't decode event: {}", e)))?;
+
+    Ok(Some(event))
+}
+
+// None on clean EOF before the first byte of the prefix
+async fn read_varint<R: AsyncRead + Unpin + Send>(reader: &mut R) -> Result<Option<u64>> {
+    let mut value: u64 = 0;
+
+    for idx in 0..MAX_VARINT_LEN {
+        let byte = match reader.read_u8().await {
+            Ok(b) => b,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof && idx == 0 => return Ok(None),
+            Err(e) => {
+                return Err(EventsFileError(format!("can't read length prefix: {}", e)).into())
+            }
+        };
+
+        value |= ((byte & 0x7f) as u64) << (idx * 7);
+
+        if byte & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+
+    Err(EventsFileError("invalid length prefix".to_owned()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use waves_protobuf_schemas::waves::events::BlockchainUpdated;
+
+    fn event(height: i32) -> SubscribeEvent {
+        SubscribeEvent {
+            update: Some(BlockchainUpdated {
+                height,
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn write_events(name: &str, heights: &[i32]) -> PathBuf {
+        let mut buf = vec![];
+        for h in heights {
+            event(*h).encode_length_delimited(&mut buf).unwrap();
+        }
+
+        let path = std::env::temp_dir().join(format!("{}-{}.bin", name, std::process::id()));
+        std::fs::write(&path, buf).unwrap();
+        path
+    }
+
+    async fn read_heights(source: &mut FileSource) -> Vec<i32> {
+        let mut heights = vec![];
+        while let Some(event) = source.next_event().await.unwrap() {
+            heights.push(event.update.unwrap().height);
+        }
+        heights
+    }
+
+    #[tokio::test]
+    async fn length_prefix_of_several_bytes() {
+        let event = SubscribeEvent {
+            update: Some(BlockchainUpdated {
+                id: vec![1; 300],
+                ..Default::default()
+            }),
+        };
+        let mut buf = vec![];
+        event.encode_length_delimited(&mut buf).unwrap();
+        assert_ne!(buf[0] & 0x80, 0);
+
+        let read = read_delimited(&mut &buf[..]).await.unwrap();
+        assert_eq!(read, Some(event));
+    }
+
+    #[tokio::test]
+    async fn only_eof_between_events_ends_the_file() {
+        let mut buf = vec![];
+        event(1).encode_length_delimited(&mut buf).unwrap();
+
+        assert!(read_delimited(&mut &buf[..0]).await.unwrap().is_none());
+        // cut inside the event and inside the length prefix
+        assert!(read_delimited(&mut &buf[..buf.len() - 1]).await.is_err());
+        assert!(read_delimited(&mut &[0x80u8][..]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rollbacks_after_from_height_are_replayed() {
+        // 4 is rolled back to 3 after the start height
+        let path = write_events("replay", &[1, 2, 3, 4, 3, 4, 5]);
+        let mut source = FileSource::new(&path);
+
+        source.subscribe(3, None).await.unwrap();
+        assert_eq!(read_heights(&mut source).await, vec![3, 4, 3, 4, 5]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resubscribe_after_partial_consumption() {
+        let path = write_events("resubscribe", &[1, 2, 3, 4, 5]);
+        let mut source = FileSource::new(&path);
+
+        source.subscribe(1, None).await.unwrap();
+        source.next_event().await.unwrap();
+        source.next_event().await.unwrap();
+
+        // reconnect resumes from the height after the last saved block
+        source.subscribe(3, None).await.unwrap();
+        assert_eq!(read_heights(&mut source).await, vec![3, 4, 5]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn segment_ends_at_first_block_above_to_height() {
+        let path = write_events("segment", &[1, 2, 3, 4, 3, 4, 5]);
+        let mut source = FileSource::new(&path);
+
+        // the rollback to 3 recorded after 4 is not part of the segment
+        source.subscribe(2, Some(3)).await.unwrap();
+        assert_eq!(read_heights(&mut source).await, vec![2, 3]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn broken_events_file_is_not_retried() {
+        let path = write_events("broken", &[1, 2]);
+        let mut buf = std::fs::read(&path).unwrap();
+        buf.pop();
+        std::fs::write(&path, buf).unwrap();
+        let mut source = FileSource::new(&path);
+
+        source.subscribe(1, None).await.unwrap();
+        source.next_event().await.unwrap();
+        let err = source.next_event().await.unwrap_err();
+        assert!(!is_transient(&err));
+
+        std::fs::remove_file(&path).unwrap();
+        let err = source.subscribe(1, None).await.unwrap_err();
+        assert!(!is_transient(&err));
+    }
+
+    #[test]
+    fn grpc_status_decides_retry() {
+        let unavailable = anyhow::Error::from(tonic::Status::unavailable("node restart"));
+        let invalid = anyhow::Error::from(tonic::Status::invalid_argument("height"));
+        // await timeout of the stream wraps no status
+        let timeout = anyhow!("event stream await timeout");
+
+        assert!(is_transient(&unavailable));
+        assert!(!is_transient(&invalid));
+        assert!(is_transient(&timeout));
+    }
+}
@@ -0,0 +1,121 @@
+use super::GRPC_STREAM_AWAIT_TIMEOUT_SECS;
+use crate::waves::BlockchainUpdateInfo;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use warp::http::StatusCode;
+
+lazy_static! {
+    pub static ref HEALTH: Health = Health::default();
+}
+
+// state of consumer tasks, updated by the tasks themselves
+#[derive(Debug, Default)]
+pub struct Health {
+    stream_connected: AtomicBool,
+    last_block_height: AtomicI64,
+    // ms, 0 until the first block
+    last_block_timestamp: AtomicI64,
+    last_event_received: AtomicI64,
+    analyzers: Mutex<BTreeMap<&'static str, bool>>,
+    exporter_running: AtomicBool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Status {
+    pub stream_connected: bool,
+    pub last_block_height: i64,
+    pub last_block_timestamp: i64,
+    pub last_event_received: i64,
+    // chunk analyzer tasks by name
+    pub analyzers: BTreeMap<&'static str, bool>,
+    pub asset_distribution_exporter: bool,
+}
+
+impl Health {
+    pub fn set_stream_connected(&self, connected: bool) {
+        self.stream_connected.store(connected, Ordering::Relaxed);
+    }
+
+    pub fn event_received(&self, block: &BlockchainUpdateInfo) {
+        self.last_event_received.store(now_ms(), Ordering::Relaxed);
+
+        if let Some(height) = block.height {
+            self.last_block_height
+                .store(height as i64, Ordering::Relaxed);
+        }
+
+        // microblocks have no timestamp
+        if let Some(timestamp) = block.timestamp {
+            self.last_block_timestamp
+                .store(timestamp, Ordering::Relaxed);
+        }
+    }
+
+    pub fn set_analyzer_alive(&self, name: &'static str, alive: bool) {
+        self.analyzers.lock().unwrap().insert(name, alive);
+    }
+
+    pub fn set_exporter_running(&self, running: bool) {
+        self.exporter_running.store(running, Ordering::Relaxed);
+    }
+
+    pub fn status(&self) -> Status {
+        Status {
+            stream_connected: self.stream_connected.load(Ordering::Relaxed),
+            last_block_height: self.last_block_height.load(Ordering::Relaxed),
+            last_block_timestamp: self.last_block_timestamp.load(Ordering::Relaxed),
+            last_event_received: self.last_event_received.load(Ordering::Relaxed),
+            analyzers: self.analyzers.lock().unwrap().clone(),
+            asset_distribution_exporter: self.exporter_running.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Status {
+    // every task is running
+    pub fn is_healthy(&self) -> bool {
+        self.analyzers.values().all(|alive| *alive) && self.asset_distribution_exporter
+    }
+
+    // stream is connected and doesn't stall
+    pub fn is_ready(&self) -> bool {
+        let max_silence_ms = GRPC_STREAM_AWAIT_TIMEOUT_SECS as i64 * 1000;
+
+        self.is_healthy()
+            && self.stream_connected
+            && now_ms() - self.last_event_received < max_silence_ms
+    }
+}
+
+pub fn health_reply() -> impl warp::Reply {
+    let status = HEALTH.status();
+    let code = status_code(status.is_healthy());
+
+    warp::reply::with_status(warp::reply::json(&status), code)
+}
+
+pub fn ready_reply() -> impl warp::Reply {
+    let status = HEALTH.status();
+    let code = status_code(status.is_ready());
+
+    warp::reply::with_status(warp::reply::json(&status), code)
+}
+
+fn status_code(ok: bool) -> StatusCode {
+    if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
@@ -0,0 +1,230 @@
+use crate::waves::{BlockType, BlockchainUpdateInfo};
+
+// events to be saved in solidified-only mode
+pub enum Confirmed {
+    // solid block for blocks_microblocks and events of its key block and microblocks
+    Block(BlockchainUpdateInfo, Vec<BlockchainUpdateInfo>),
+    // rollback to an already saved block
+    Rollback(BlockchainUpdateInfo),
+    // a saved block got new microblocks after rollback or the liquid block doesn't match the next block,
+    // blocks must be received again from this height
+    Refetch(u32),
+}
+
+// keeps the liquid block (key block and its microblocks) in memory until the next block confirms it
+#[derive(Default)]
+pub struct LiquidBlock {
+    events: Vec<BlockchainUpdateInfo>,
+}
+
+impl LiquidBlock {
+    pub fn push(&mut self, block: BlockchainUpdateInfo) -> Option<Confirmed> {
+        match block.block_type {
+            BlockType::Block => {
+                let confirmed = match block.reference_block_id.clone() {
+                    Some(reference) => self.confirm(reference),
+                    None => None,
+                };
+
+                if !matches!(confirmed, Some(Confirmed::Refetch(_))) {
+                    self.events.push(block);
+                }
+
+                confirmed
+            }
+            BlockType::MicroBlock => {
+                if self.events.is_empty() {
+                    return block.height.map(Confirmed::Refetch);
+                }
+                self.events.push(block);
+
+                None
+            }
+            BlockType::Rollback => match self.events.iter().position(|e| e.id == block.id) {
+                Some(idx) => {
+                    self.events.truncate(idx + 1);
+                    None
+                }
+                None => {
+                    self.events.clear();
+                    Some(Confirmed::Rollback(block))
+                }
+            },
+            BlockType::EMPTY => None,
+        }
+    }
+
+    // the liquid block is final when nothing follows it (bounded ingestion)
+    pub fn finish(&mut self) -> Option<Confirmed> {
+        let id = self.events.last().and_then(|e| e.id.clone());
+
+        self.take(id)
+    }
+
+    // the next block references the last part of the liquid block it is built on
+    fn confirm(&mut self, reference: String) -> Option<Confirmed> {
+        if self.events.is_empty() {
+            return None;
+        }
+
+        match self
+            .events
+            .iter()
+            .position(|e| e.id.as_deref() == Some(reference.as_str()))
+        {
+            Some(idx) => self.events.truncate(idx + 1),
+            // a part the block is built on was missed, buffered parts can't be saved under its id
+            None => {
+                let height = self.events[0].height;
+                self.events.clear();
+
+                return height.map(Confirmed::Refetch);
+            }
+        }
+
+        self.take(Some(reference))
+    }
+
+    // solid block gets the id of the last microblock, as blocks_microblocks::solidify does
+    fn take(&mut self, id: Option<String>) -> Option<Confirmed> {
+        if self.events.is_empty() {
+            return None;
+        }
+
+        let mut parts = std::mem::take(&mut self.events);
+        let key_block = &parts[0];
+
+        let header = BlockchainUpdateInfo {
+            id: id,
+            height: key_block.height,
+            timestamp: key_block.timestamp,
+            reference_block_id: key_block.reference_block_id.clone(),
+            block_type: BlockType::Block,
+            ..Default::default()
+        };
+
+        // chunk analyzers see one block
+        parts
+            .iter_mut()
+            .for_each(|p| p.block_type = BlockType::Block);
+
+        Some(Confirmed::Block(header, parts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(block_type: BlockType, id: &str, height: u32) -> BlockchainUpdateInfo {
+        BlockchainUpdateInfo {
+            id: Some(id.to_owned()),
+            height: Some(height),
+            block_type,
+            ..Default::default()
+        }
+    }
+
+    fn block(id: &str, height: u32, reference: &str) -> BlockchainUpdateInfo {
+        BlockchainUpdateInfo {
+            timestamp: Some(height as i64 * 60_000),
+            reference_block_id: Some(reference.to_owned()),
+            ..event(BlockType::Block, id, height)
+        }
+    }
+
+    fn part_ids(confirmed: Option<Confirmed>) -> (BlockchainUpdateInfo, Vec<String>) {
+        match confirmed {
+            Some(Confirmed::Block(header, parts)) => {
+                assert!(parts.iter().all(|p| p.block_type == BlockType::Block));
+                (header, parts.into_iter().map(|p| p.id.unwrap()).collect())
+            }
+            _ => panic!("solid block expected"),
+        }
+    }
+
+    // key block a at height 1 with microblocks a1, a2 built on saved block z
+    fn liquid() -> LiquidBlock {
+        let mut liquid = LiquidBlock::default();
+
+        assert!(liquid.push(block("a", 1, "z")).is_none());
+        assert!(liquid.push(event(BlockType::MicroBlock, "a1", 1)).is_none());
+        assert!(liquid.push(event(BlockType::MicroBlock, "a2", 1)).is_none());
+
+        liquid
+    }
+
+    #[test]
+    fn solid_block_takes_id_of_last_microblock_and_key_block_header() {
+        let mut liquid = liquid();
+
+        let (header, parts) = part_ids(liquid.push(block("b", 2, "a2")));
+
+        assert_eq!(header.id.as_deref(), Some("a2"));
+        assert_eq!(header.height, Some(1));
+        assert_eq!(header.timestamp, Some(60_000));
+        assert_eq!(header.reference_block_id.as_deref(), Some("z"));
+        assert_eq!(parts, vec!["a", "a1", "a2"]);
+
+        // b is kept as the new liquid block
+        let (header, parts) = part_ids(liquid.finish());
+        assert_eq!(header.id.as_deref(), Some("b"));
+        assert_eq!(parts, vec!["b"]);
+    }
+
+    #[test]
+    fn microblocks_after_referenced_one_are_dropped() {
+        let mut liquid = liquid();
+
+        let (header, parts) = part_ids(liquid.push(block("b", 2, "a1")));
+
+        assert_eq!(header.id.as_deref(), Some("a1"));
+        assert_eq!(parts, vec!["a", "a1"]);
+    }
+
+    #[test]
+    fn unknown_reference_refetches_liquid_block() {
+        // a3 was missed, the next block is built on it
+        let mut liquid = liquid();
+
+        let refetch = liquid.push(block("b", 2, "a3"));
+
+        assert!(matches!(refetch, Some(Confirmed::Refetch(1))));
+        // neither the buffered parts nor b are kept
+        assert!(liquid.finish().is_none());
+        let refetch = liquid.push(event(BlockType::MicroBlock, "b1", 2));
+        assert!(matches!(refetch, Some(Confirmed::Refetch(2))));
+    }
+
+    #[test]
+    fn microblock_received_before_any_block_is_refetched() {
+        // stream started in the middle of a liquid block
+        let mut liquid = LiquidBlock::default();
+
+        let refetch = liquid.push(event(BlockType::MicroBlock, "a1", 1));
+
+        assert!(matches!(refetch, Some(Confirmed::Refetch(1))));
+    }
+
+    #[test]
+    fn rollback_to_microblock_is_applied_in_memory() {
+        let mut liquid = liquid();
+
+        assert!(liquid.push(event(BlockType::Rollback, "a1", 1)).is_none());
+        assert!(liquid.push(event(BlockType::MicroBlock, "a3", 1)).is_none());
+
+        let (_, parts) = part_ids(liquid.finish());
+        assert_eq!(parts, vec!["a", "a1", "a3"]);
+    }
+
+    #[test]
+    fn rollback_below_liquid_block_is_passed_on() {
+        let mut liquid = liquid();
+
+        match liquid.push(event(BlockType::Rollback, "z", 0)) {
+            Some(Confirmed::Rollback(r)) => assert_eq!(r.id.as_deref(), Some("z")),
+            _ => panic!("rollback expected"),
+        }
+        assert!(liquid.finish().is_none());
+    }
+}
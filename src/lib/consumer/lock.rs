@@ -0,0 +1,66 @@
+use crate::db::{mappers::advisory_locks, Db};
+use anyhow::anyhow;
+use std::time::Duration;
+use wavesexchange_log::{info, warn};
+
+const LOCK_RETRY_SECS: u64 = 10;
+const LOCK_CHECK_SECS: u64 = 5;
+
+// takes a session level advisory lock on db connection, so only one instance writes;
+// waits for the lock to be released (hot standby) or fails at once
+pub async fn acquire(db: &Db, key: i64, name: &str, wait: bool) -> Result<(), anyhow::Error> {
+    let mut logged = false;
+
+    while !advisory_locks::try_lock(db, key).await? {
+        let holder = match advisory_locks::holder_pid(db, key).await? {
+            Some(pid) => format!("backend pid {}", pid),
+            None => "unknown backend".to_owned(),
+        };
+
+        if !wait {
+            return Err(anyhow!(
+                "{} lock is held by another instance ({}), exiting",
+                name,
+                holder
+            ));
+        }
+
+        if !logged {
+            info!(
+                "{} lock is held by another instance ({}), waiting for it",
+                name, holder
+            );
+            logged = true;
+        }
+
+        tokio::time::sleep(Duration::from_secs(LOCK_RETRY_SECS)).await;
+    }
+
+    info!("{} lock acquired", name);
+
+    Ok(())
+}
+
+// postgres releases the lock with the connection and another instance may take it over,
+// so the lock holder must stop writing; returns only when the lock is lost
+pub async fn watch(db: &Db, name: &str) -> Result<(), anyhow::Error> {
+    let check_interval = Duration::from_secs(LOCK_CHECK_SECS);
+
+    loop {
+        tokio::time::sleep(check_interval).await;
+
+        // a hanging connection may already be dropped on the server side
+        match tokio::time::timeout(check_interval * 2, db.query("select 1", &[])).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) if !db.is_closed() => {
+                warn!("{} lock connection check failed: {}", name, err);
+            }
+            Ok(Err(err)) => {
+                return Err(anyhow!("{} lock is lost, connection closed: {}", name, err));
+            }
+            Err(_) => {
+                return Err(anyhow!("{} lock is lost, connection doesn't respond", name));
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use super::health;
+use anyhow::Context;
+use lazy_static::lazy_static;
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, register_int_gauge_vec, Encoder, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, IntGaugeVec, TextEncoder,
+};
+use std::future::Future;
+use warp::Filter;
+use wavesexchange_log::info;
+
+lazy_static! {
+    pub static ref BALANCE_DISCREPANCIES: IntCounter = register_int_counter!(
+        "balance_discrepancies_total",
+        "Balance updates with amount_before not equal to the last stored amount"
+    )
+    .unwrap();
+    pub static ref CURRENT_HEIGHT: IntGauge =
+        register_int_gauge!("consumer_height", "Height of the last processed block").unwrap();
+    pub static ref EVENTS_PROCESSED: IntCounterVec = register_int_counter_vec!(
+        "consumer_events_total",
+        "Processed blocks, microblocks and rollbacks",
+        &["block_type"]
+    )
+    .unwrap();
+    pub static ref CHUNK_FLUSH_DURATION: HistogramVec = register_histogram_vec!(
+        "consumer_chunk_flush_duration_seconds",
+        "Time to save a chunk including retries",
+        &["chunk"]
+    )
+    .unwrap();
+    pub static ref CHUNK_FLUSH_ROWS: HistogramVec = register_histogram_vec!(
+        "consumer_chunk_flush_rows",
+        "Rows in a saved chunk",
+        &["chunk"],
+        exponential_buckets(1.0, 4.0, 10).unwrap()
+    )
+    .unwrap();
+    pub static ref CHUNK_FLUSHES: IntCounterVec = register_int_counter_vec!(
+        "consumer_chunk_flushes_total",
+        "Saved chunks by the limit of the flush policy that triggered the save",
+        &["chunk", "reason"]
+    )
+    .unwrap();
+    pub static ref ANALYZER_BACKLOG: IntGaugeVec = register_int_gauge_vec!(
+        "consumer_analyzer_backlog",
+        "Blocks waiting in the channel of a chunk analyzer",
+        &["chunk"]
+    )
+    .unwrap();
+    pub static ref DISTRIBUTION_TASKS_QUEUED: IntGauge = register_int_gauge!(
+        "asset_distribution_tasks_queued",
+        "Asset distribution tasks waiting to be processed"
+    )
+    .unwrap();
+}
+
+// /metrics in prometheus text format and /health, /ready with json status of consumer tasks;
+// the port is bound at once, so a busy port is an error on start
+pub fn bind(port: u16) -> Result<impl Future<Output = ()>, anyhow::Error> {
+    let metrics = warp::path!("metrics").and(warp::get()).map(|| {
+        let mut buf = vec![];
+        TextEncoder::new()
+            .encode(&prometheus::gather(), &mut buf)
+            .unwrap();
+        buf
+    });
+
+    let health = warp::path!("health")
+        .and(warp::get())
+        .map(health::health_reply);
+
+    let ready = warp::path!("ready")
+        .and(warp::get())
+        .map(health::ready_reply);
+
+    let (addr, server) = warp::serve(metrics.or(health).or(ready))
+        .try_bind_ephemeral(([0, 0, 0, 0], port))
+        .with_context(|| format!("can't bind consumer metrics port {}", port))?;
+
+    info!("Starting consumer metrics listening on {}", addr);
+
+    Ok(server)
+}
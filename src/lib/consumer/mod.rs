@@ -1,81 +1,320 @@
+pub mod backfill;
+pub mod backoff;
+pub mod event_source;
+pub mod health;
+pub mod liquid;
+pub mod lock;
+pub mod metrics;
+pub mod reindex;
 pub mod settings;
+pub mod shutdown;
 use anyhow::Result;
-use bu::balance_updates::Analyzer as BalanceAnalyzer;
+use backoff::Backoff;
+use bu::balance_updates::BalanceChunk;
 use bu::blocks::Analyzer as BlockAnalyzer;
+use bu::chunk_analyzer::{Chunk, ChunkAnalyzer};
+use bu::data_entries::DataEntriesChunk;
+use bu::filters::IngestFilter;
+use event_source::{EventSource, FileSource, GrpcSource};
 use lazy_static::lazy_static;
+use liquid::{Confirmed, LiquidBlock};
 use settings::Settings;
 use std::time::Instant;
 use tokio::{
     select,
+    sync::watch,
     time::{self as tokio_time, Duration as tokio_duration, Instant as tokio_instant},
 };
 use wavesexchange_log::{error, info, warn};
 
-use waves_protobuf_schemas::waves::events::grpc::{
-    blockchain_updates_api_client::BlockchainUpdatesApiClient, SubscribeRequest,
-};
-
-use crate::waves::{bu, BlockchainUpdateInfo};
+use crate::db::*;
+use crate::waves::{bu, BlockType, BlockchainUpdateInfo};
 pub const SAFE_HEIGHT_OFFSET: u32 = 20;
 pub const GRPC_STREAM_AWAIT_TIMEOUT_SECS: u64 = 300;
+pub const RECONNECT_BACKOFF_INITIAL_MS: u64 = 500;
+pub const RECONNECT_BACKOFF_MAX_SECS: u64 = 60;
+// time given to the asset distribution task in progress on shutdown
+const SHUTDOWN_EXPORTER_TIMEOUT_SECS: u64 = 60;
+// blocks in channels of chunk analyzers
+const ANALYZER_BUF_SIZE: usize = 1000;
+
+// how the event stream consumption ended
+enum StreamEnd {
+    Exhausted,
+    TargetReached,
+    Interrupted(anyhow::Error),
+    // normal on the chain tip (microblock fork), resubscribed at once
+    Resubscribe,
+    Shutdown,
+}
 
 lazy_static! {
     pub static ref SETTINGS: Settings = Settings::init();
 }
 
-pub async fn run(
-    blockchain_updates_url: impl AsRef<str> + Send + 'static,
-    start_height: i32,
-) -> Result<(), anyhow::Error> {
-    let consumer_handle = tokio::spawn(async move {
-        let url: String = blockchain_updates_url.as_ref().into();
-        run_blockchain_analyze(url, start_height).await
+// file replay if configured, grpc otherwise
+pub fn new_event_source() -> Box<dyn EventSource> {
+    match &SETTINGS.config.blockchain_updates_file {
+        Some(path) => Box::new(FileSource::new(path)),
+        None => Box::new(GrpcSource::new(
+            SETTINGS.config.blockchain_updates_url.clone(),
+        )),
+    }
+}
+
+// one chunk per history table group, each is saved in its own transaction;
+// amount_before continuity is checked only by the live consumer which saves blocks in order
+pub fn new_chunks(filter: &IngestFilter, check_continuity: bool) -> Vec<Box<dyn Chunk>> {
+    let id_cache_size = SETTINGS.config.id_cache_size;
+    let balances = BalanceChunk::new(filter.clone()).with_id_cache_size(id_cache_size);
+
+    let balances = if check_continuity {
+        balances.with_continuity_check()
+    } else {
+        balances
+    };
+
+    vec![
+        Box::new(balances),
+        Box::new(DataEntriesChunk::new(filter.clone()).with_id_cache_size(id_cache_size)),
+    ]
+}
+
+pub async fn run(mut source: Box<dyn EventSource>, start_height: i32) -> Result<(), anyhow::Error> {
+    let metrics_server = metrics::bind(SETTINGS.config.metrics_port)?;
+    tokio::spawn(metrics_server);
+
+    let shutdown = shutdown::listen();
+
+    let consumer_shutdown = shutdown.clone();
+    let mut consumer_handle = tokio::spawn(async move {
+        run_blockchain_analyze(source.as_mut(), start_height, consumer_shutdown).await
     });
 
-    let distribution_handle = tokio::spawn(async move { run_asset_distribution_exporter().await });
+    let distribution_shutdown = shutdown.clone();
+    let mut distribution_handle = tokio::spawn(async move {
+        health::HEALTH.set_exporter_running(true);
+        let res = run_asset_distribution_exporter(distribution_shutdown).await;
+        health::HEALTH.set_exporter_running(false);
+
+        if let Err(err) = &res {
+            error!("asset distribution exporter stopped: {:?}", err);
+        }
+
+        res
+    });
+
+    let mut exporter_done = false;
+
+    let res = loop {
+        select! {
+            ce = &mut consumer_handle => {
+                break match ce {
+                    Err(err) => {
+                        panic!("consumer handler panic: {}", err);
+                    },
+                    Ok(Ok(())) => {
+                        info!("consumer finished");
+                        Ok(())
+                    }
+                    Ok(Err(err)) => {
+                        error!("consumer stopped: {:?}", err);
+                        Err(err)
+                    }
+                };
+            },
+            de = &mut distribution_handle, if !exporter_done => {
+                // consumer keeps running when the exporter stops with an error, health reports it
+                exporter_done = true;
 
-    select! {
-        ce = consumer_handle => {
-            match ce {
-                Err(err) => {
-                    panic!("consumer handler panic: {}", err);
-                },
-                Ok(_) => {
-                    panic!("consumer handler exit without error");
+                if let Err(err) = de {
+                    panic!("asset distribution handler panic: {}", err);
                 }
             }
-
-        },
-        Err(err) = distribution_handle => {
-            panic!("asset distribution handler panic: {}", err);
         }
+    };
+
+    if !exporter_done && shutdown::is_requested(&shutdown) {
+        stop_exporter(distribution_handle).await?;
     }
+
+    res
+}
+
+// lets the task in progress finish, otherwise it is marked as interrupted
+async fn stop_exporter(
+    mut handle: tokio::task::JoinHandle<Result<(), anyhow::Error>>,
+) -> Result<(), anyhow::Error> {
+    let timeout = tokio_duration::from_secs(SHUTDOWN_EXPORTER_TIMEOUT_SECS);
+
+    if tokio_time::timeout(timeout, &mut handle).await.is_ok() {
+        info!("asset distribution exporter stopped");
+        return Ok(());
+    }
+
+    // transaction of the task is rolled back when the task is dropped
+    handle.abort();
+    let _ = handle.await;
+
+    let db = Db::new(&SETTINGS.config.postgres).await?;
+    mappers::distribution_task::interrupt_in_progress(&db).await?;
+
+    Ok(())
 }
 
 async fn run_blockchain_analyze(
-    blockchain_updates_url: impl AsRef<str>,
+    source: &mut dyn EventSource,
     start_height: i32,
+    mut shutdown: watch::Receiver<bool>,
 ) -> Result<(), anyhow::Error> {
-    let request = tonic::Request::new(SubscribeRequest {
-        from_height: start_height,
-        to_height: 0,
-    });
-
     info!(
         "Starting balances-consumer: {}; start height: {}",
-        blockchain_updates_url.as_ref(),
-        start_height
+        source, start_height
     );
 
-    let mut stream =
-        BlockchainUpdatesApiClient::connect(blockchain_updates_url.as_ref().to_owned())
-            .await?
-            .subscribe(request)
-            .await?
-            .into_inner();
+    let filter = {
+        let db = Db::new(&SETTINGS.config.postgres).await?;
+        IngestFilter::load(&db, &SETTINGS.config.ingest_filter).await?
+    };
 
-    let mut block_analyzer = BlockAnalyzer::new().await;
-    let balance_analyzer = BalanceAnalyzer::new(1000).await;
+    // deep history is loaded by parallel workers, live consumer continues after it;
+    // backfill stops between batches on shutdown, after restart it continues from safe heights
+    let backfill_to_height = match (
+        SETTINGS.config.backfill.to_height,
+        SETTINGS.config.blockchain_to_height,
+    ) {
+        (Some(backfill_to), Some(to_height)) => Some(std::cmp::min(backfill_to, to_height)),
+        (backfill_to, _) => backfill_to,
+    };
+
+    let start_height = match backfill_to_height {
+        Some(to_height) if start_height <= to_height => {
+            match backfill::run(start_height, to_height, &filter, shutdown.clone()).await? {
+                Some(height) => height,
+                None => {
+                    info!("consumer stopped during backfill");
+                    return Ok(());
+                }
+            }
+        }
+        _ => start_height,
+    };
+
+    if matches!(SETTINGS.config.blockchain_to_height, Some(to_height) if start_height > to_height) {
+        info!("target height is already reached, nothing to ingest");
+        return Ok(());
+    }
+
+    // a chunk analyzer waiting for a batch to be committed doesn't read its channel,
+    // so the batch must fit into the channel
+    let blocks_batch_size = std::cmp::min(SETTINGS.config.blocks_batch_size, ANALYZER_BUF_SIZE / 2);
+    let mut block_analyzer = BlockAnalyzer::new(blocks_batch_size).await?;
+
+    let mut analyzers = vec![];
+    for chunk in new_chunks(&filter, true) {
+        analyzers.push(
+            ChunkAnalyzer::new(chunk, ANALYZER_BUF_SIZE, block_analyzer.committed_uid()).await,
+        );
+    }
+
+    let mut backoff = Backoff::new(
+        tokio_duration::from_millis(RECONNECT_BACKOFF_INITIAL_MS),
+        tokio_duration::from_secs(RECONNECT_BACKOFF_MAX_SECS),
+    );
+
+    let mut from_height = start_height;
+    let mut stopped = false;
+
+    loop {
+        let stream_end = consume_stream(
+            source,
+            from_height,
+            &mut block_analyzer,
+            &mut analyzers,
+            &mut backoff,
+            &mut shutdown,
+        )
+        .await;
+
+        health::HEALTH.set_stream_connected(false);
+
+        match stream_end? {
+            StreamEnd::Exhausted | StreamEnd::TargetReached => break,
+            StreamEnd::Shutdown => {
+                stopped = true;
+                break;
+            }
+            StreamEnd::Resubscribe => {
+                from_height = resume(&mut block_analyzer, &mut analyzers, start_height).await?;
+            }
+            StreamEnd::Interrupted(err) => {
+                let delay = backoff.next_delay();
+                warn!(
+                    "event stream interrupted: {}; reconnecting in {} ms",
+                    err,
+                    delay.as_millis()
+                );
+
+                select! {
+                    _ = tokio_time::sleep(delay) => {},
+                    Ok(()) = shutdown.changed() => {
+                        stopped = true;
+                        break;
+                    }
+                }
+
+                // resubscribe from the last solidified block, only unsolidified tail is dropped
+                from_height = resume(&mut block_analyzer, &mut analyzers, start_height).await?;
+            }
+        }
+    }
+
+    // analyzers wait for their blocks to be committed
+    block_analyzer.flush().await?;
+
+    // remaining blocks in channels are processed and last chunks are saved with safe heights
+    for analyzer in analyzers {
+        analyzer.finish().await?;
+    }
+
+    if stopped {
+        info!("consumer stopped, pending chunks are saved");
+        return Ok(());
+    }
+
+    if is_bounded() {
+        block_analyzer.solidify_all().await?;
+    }
+
+    Ok(())
+}
+
+// transient stream errors end up in StreamEnd::Interrupted and lead to resubscribe, other errors are fatal
+async fn consume_stream(
+    source: &mut dyn EventSource,
+    from_height: i32,
+    block_analyzer: &mut BlockAnalyzer,
+    analyzers: &mut [ChunkAnalyzer],
+    backoff: &mut Backoff,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Result<StreamEnd, anyhow::Error> {
+    if shutdown::is_requested(shutdown) {
+        return Ok(StreamEnd::Shutdown);
+    }
+
+    info!("subscribing to {} from height {}", source, from_height);
+
+    if let Err(err) = source
+        .subscribe(from_height, SETTINGS.config.blockchain_to_height)
+        .await
+    {
+        return stream_error(err);
+    }
+
+    health::HEALTH.set_stream_connected(true);
+
+    // solidified-only mode: only blocks confirmed by the next block are saved
+    let mut liquid = SETTINGS.config.solidified_only.then(LiquidBlock::default);
 
     let sleep_duration = tokio_duration::from_secs(GRPC_STREAM_AWAIT_TIMEOUT_SECS);
     let sleep = tokio_time::sleep(sleep_duration);
@@ -84,54 +323,319 @@ async fn run_blockchain_analyze(
     loop {
         sleep.as_mut().reset(tokio_instant::now() + sleep_duration);
 
-        let mut block: BlockchainUpdateInfo;
+        let msg = select! {
+                msg = source.next_event() => msg,
 
-        select! {
-                msg = stream.message() => block = msg?.into(),
+                Ok(()) = shutdown.changed() => return Ok(StreamEnd::Shutdown),
 
                 _ = &mut sleep => {
-                error!("grpc stream message await timeout for {} seconds. Exiting.", GRPC_STREAM_AWAIT_TIMEOUT_SECS);
-                break;
+                error!("grpc stream message await timeout for {} seconds", GRPC_STREAM_AWAIT_TIMEOUT_SECS);
+                return Ok(StreamEnd::Interrupted(anyhow::anyhow!("event stream await timeout")));
+            }
+        };
+
+        let block: BlockchainUpdateInfo = match msg {
+            Ok(Some(event)) => Some(event).into(),
+            Ok(None) => {
+                save_last_liquid_block(liquid.as_mut(), block_analyzer, analyzers).await?;
+                return Ok(StreamEnd::Exhausted);
             }
+            Err(err) => return stream_error(err),
+        };
+
+        backoff.reset();
+        health::HEALTH.event_received(&block);
+
+        if is_beyond_target(
+            &block,
+            SETTINGS.config.blockchain_to_height,
+            SETTINGS.config.blockchain_to_timestamp,
+        ) {
+            info!(
+                "target reached: {} height {} is not ingested",
+                block.block_type,
+                block.height.clone().unwrap()
+            );
+            save_last_liquid_block(liquid.as_mut(), block_analyzer, analyzers).await?;
+            return Ok(StreamEnd::TargetReached);
         }
 
-        let processing_start = Instant::now();
+        let confirmed = match liquid.as_mut() {
+            Some(liquid) => liquid.push(block),
+            None => {
+                save_event(block_analyzer, analyzers, block).await?;
+                continue;
+            }
+        };
 
-        let block_uid = block_analyzer.send(&block).await;
-        block.uid = Some(block_uid);
+        match confirmed {
+            Some(Confirmed::Block(header, parts)) => {
+                save_solid_block(block_analyzer, analyzers, header, parts).await?
+            }
+            Some(Confirmed::Rollback(rollback)) => {
+                save_event(block_analyzer, analyzers, rollback).await?
+            }
+            Some(Confirmed::Refetch(height)) => {
+                // blocks from the height are rolled back and received again with all their microblocks
+                let parent_id = block_analyzer
+                    .block_id(height as i32 - 1)
+                    .await?
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "block at height {} to refetch from is not found",
+                            height - 1
+                        )
+                    })?;
 
-        balance_analyzer.send(&block).await;
+                let rollback = BlockchainUpdateInfo {
+                    id: Some(parent_id),
+                    height: Some(height - 1),
+                    block_type: BlockType::Rollback,
+                    ..Default::default()
+                };
+                save_event(block_analyzer, analyzers, rollback).await?;
 
-        let processing_end = Instant::now();
-        let processing_duration = processing_end.duration_since(processing_start);
+                info!(
+                    "blocks from height {} are fetched again, resubscribing",
+                    height
+                );
 
-        info!(
-            "block_uid: {}; height {}; {} id: {}; processed: {} ms;",
-            block.uid.as_ref().unwrap(),
-            block.height.clone().unwrap(),
-            block.block_type,
-            block.id.clone().unwrap(),
-            processing_duration.as_millis(),
-        );
+                return Ok(StreamEnd::Resubscribe);
+            }
+            None => {}
+        }
+    }
+}
+
+// chunk analyzers drop rows and cached amounts of the deleted tail as on rollback,
+// otherwise blocks received again are compared with amounts of the deleted ones
+async fn resume(
+    block_analyzer: &mut BlockAnalyzer,
+    analyzers: &mut [ChunkAnalyzer],
+    start_height: i32,
+) -> Result<i32, anyhow::Error> {
+    let (from_height, last_uid) = block_analyzer.resume(start_height).await?;
+
+    if let Some(last_uid) = last_uid {
+        let rollback = BlockchainUpdateInfo {
+            uid: Some(last_uid),
+            height: Some(std::cmp::max(from_height - 1, 0) as u32),
+            block_type: BlockType::Rollback,
+            ..Default::default()
+        };
+
+        for analyzer in analyzers.iter_mut() {
+            analyzer.send(&rollback).await?;
+        }
+    }
+
+    Ok(from_height)
+}
+
+fn stream_error(err: anyhow::Error) -> Result<StreamEnd, anyhow::Error> {
+    if event_source::is_transient(&err) {
+        Ok(StreamEnd::Interrupted(err))
+    } else {
+        Err(err.context("event source failed"))
+    }
+}
+
+// saves block (with uid from the block analyzer) in all analyzers
+async fn save_event(
+    block_analyzer: &mut BlockAnalyzer,
+    analyzers: &mut [ChunkAnalyzer],
+    mut block: BlockchainUpdateInfo,
+) -> Result<(), anyhow::Error> {
+    let processing_start = Instant::now();
+
+    let block_uid = block_analyzer.send(&block).await?;
+    block.uid = Some(block_uid);
+
+    if block.block_type == BlockType::Rollback {
+        block.rollback_uid = block_analyzer.last_rollback_uid();
+    }
+
+    for analyzer in analyzers.iter_mut() {
+        analyzer.send(&block).await?;
+    }
+
+    log_processed(&block, processing_start);
+
+    Ok(())
+}
+
+// header goes to the block analyzer, parts (key block and microblocks) share its uid
+async fn save_solid_block(
+    block_analyzer: &mut BlockAnalyzer,
+    analyzers: &mut [ChunkAnalyzer],
+    mut header: BlockchainUpdateInfo,
+    parts: Vec<BlockchainUpdateInfo>,
+) -> Result<(), anyhow::Error> {
+    let processing_start = Instant::now();
+
+    let block_uid = block_analyzer.send(&header).await?;
+    header.uid = Some(block_uid);
+
+    for mut part in parts {
+        part.uid = Some(block_uid);
+
+        for analyzer in analyzers.iter_mut() {
+            analyzer.send(&part).await?;
+        }
+    }
+
+    log_processed(&header, processing_start);
+
+    Ok(())
+}
+
+// for bounded ingestion the last liquid block is final
+async fn save_last_liquid_block(
+    liquid: Option<&mut LiquidBlock>,
+    block_analyzer: &mut BlockAnalyzer,
+    analyzers: &mut [ChunkAnalyzer],
+) -> Result<(), anyhow::Error> {
+    if !is_bounded() {
+        return Ok(());
+    }
+
+    if let Some(Confirmed::Block(header, parts)) = liquid.and_then(|l| l.finish()) {
+        save_solid_block(block_analyzer, analyzers, header, parts).await?;
     }
 
     Ok(())
 }
 
-async fn run_asset_distribution_exporter() -> Result<(), anyhow::Error> {
-    use crate::db::mappers::asset_distribution;
-    use crate::db::*;
+fn log_processed(block: &BlockchainUpdateInfo, processing_start: Instant) {
+    let processing_end = Instant::now();
+    let processing_duration = processing_end.duration_since(processing_start);
+
+    metrics::EVENTS_PROCESSED
+        .with_label_values(&[&block.block_type.to_string().to_lowercase()])
+        .inc();
+    if let Some(height) = block.height {
+        metrics::CURRENT_HEIGHT.set(height as i64);
+    }
+
+    info!(
+        "block_uid: {}; height {}; {} id: {}; processed: {} ms;",
+        block.uid.as_ref().unwrap(),
+        block.height.clone().unwrap(),
+        block.block_type,
+        block.id.clone().unwrap(),
+        processing_duration.as_millis(),
+    );
+}
+
+fn is_bounded() -> bool {
+    SETTINGS.config.blockchain_to_height.is_some()
+        || SETTINGS.config.blockchain_to_timestamp.is_some()
+}
+
+// microblocks belong to the last ingested block, so only the next block ends the range
+fn is_beyond_target(
+    block: &BlockchainUpdateInfo,
+    to_height: Option<i32>,
+    to_timestamp: Option<i64>,
+) -> bool {
+    if block.block_type != BlockType::Block {
+        return false;
+    }
+
+    let beyond_height = matches!(
+        (to_height, block.height),
+        (Some(to_height), Some(height)) if height as i32 > to_height
+    );
+
+    let beyond_timestamp = matches!(
+        (to_timestamp, block.timestamp),
+        (Some(to_timestamp), Some(timestamp)) if timestamp > to_timestamp
+    );
+
+    beyond_height || beyond_timestamp
+}
+
+// stops between tasks on shutdown
+async fn run_asset_distribution_exporter(
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), anyhow::Error> {
+    use crate::db::mappers::{asset_distribution, distribution_task};
 
     let mut db = Db::new(&SETTINGS.config.postgres).await.unwrap();
 
+    lock::acquire(
+        &db,
+        mappers::advisory_locks::DISTRIBUTION_LOCK_KEY,
+        "asset distribution",
+        SETTINGS.config.lock_wait,
+    )
+    .await?;
+
     loop {
+        if shutdown::is_requested(&shutdown) {
+            return Ok(());
+        }
+
+        metrics::DISTRIBUTION_TASKS_QUEUED.set(distribution_task::count_queued(&db).await?);
+
         match asset_distribution::refresh(&mut db).await {
             Err(e) => return Err(e),
             Ok(task_processed) => {
                 if task_processed == 0 {
-                    tokio::time::sleep(std::time::Duration::from_secs(60 * 5)).await
+                    select! {
+                        _ = tokio::time::sleep(std::time::Duration::from_secs(60 * 5)) => {},
+                        Ok(()) = shutdown.changed() => {},
+                    }
                 }
             }
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(block_type: BlockType, height: u32, timestamp: Option<i64>) -> BlockchainUpdateInfo {
+        BlockchainUpdateInfo {
+            height: Some(height),
+            timestamp,
+            block_type,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn block_of_target_height_is_ingested() {
+        let at_target = event(BlockType::Block, 100, Some(1));
+        let above_target = event(BlockType::Block, 101, Some(1));
+
+        assert!(!is_beyond_target(&at_target, Some(100), None));
+        assert!(is_beyond_target(&above_target, Some(100), None));
+    }
+
+    #[test]
+    fn timestamp_bound_ends_before_height_bound() {
+        let block = event(BlockType::Block, 50, Some(1001));
+
+        assert!(is_beyond_target(&block, Some(100), Some(1000)));
+        assert!(!is_beyond_target(&block, Some(100), Some(1001)));
+    }
+
+    #[test]
+    fn microblocks_after_target_block_are_ingested() {
+        // microblocks and rollbacks carry the height of the last block and no timestamp
+        let micro = event(BlockType::MicroBlock, 101, None);
+        let rollback = event(BlockType::Rollback, 101, None);
+
+        assert!(!is_beyond_target(&micro, Some(100), Some(0)));
+        assert!(!is_beyond_target(&rollback, Some(100), Some(0)));
+    }
+
+    #[test]
+    fn block_without_timestamp_does_not_end_ingestion() {
+        let block = event(BlockType::Block, 1, None);
+
+        assert!(!is_beyond_target(&block, None, Some(0)));
+    }
+}
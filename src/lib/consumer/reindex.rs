@@ -0,0 +1,165 @@
+use super::{new_chunks, new_event_source, GRPC_STREAM_AWAIT_TIMEOUT_SECS, SETTINGS};
+use crate::db::{
+    mappers::{blocks_microblocks, safe_heights},
+    *,
+};
+use crate::waves::{
+    bu::{chunk_analyzer::Chunk, filters::IngestFilter},
+    BlockType, BlockchainUpdateInfo,
+};
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+use wavesexchange_log::info;
+
+// heights replaced in one transaction
+const BATCH_MAX_BLOCKS: usize = 100;
+
+// Re-ingests [from_height, to_height] replacing history rows of these blocks in place.
+// Blocks, later heights and unique_address/unique_assets ids are kept, rows are linked to the key block of the height.
+// Each batch is replaced atomically, so an interrupted reindex can simply be started again.
+pub async fn run(from_height: i32, to_height: i32) -> Result<()> {
+    let mut db = Db::new(&SETTINGS.config.postgres).await?;
+
+    if from_height > to_height {
+        return Err(anyhow!(
+            "invalid height range {}..={}",
+            from_height,
+            to_height
+        ));
+    }
+
+    // above the safe height blocks may still be rolled back by the consumer
+    let safe_height = safe_heights::get_min(&db).await?;
+    if to_height > safe_height {
+        return Err(anyhow!(
+            "only heights up to the safe height {} can be reindexed",
+            safe_height
+        ));
+    }
+
+    let filter = IngestFilter::load(&db, &SETTINGS.config.ingest_filter).await?;
+    let mut chunks = new_chunks(&filter, false);
+
+    let mut source = new_event_source();
+    source.subscribe(from_height, Some(to_height)).await?;
+
+    info!(
+        "reindex heights {}..={} from {}",
+        from_height, to_height, source
+    );
+
+    let await_timeout = Duration::from_secs(GRPC_STREAM_AWAIT_TIMEOUT_SECS);
+    let mut heights: Vec<i32> = vec![];
+    let mut next_height = from_height;
+
+    loop {
+        let event = tokio::time::timeout(await_timeout, source.next_event())
+            .await
+            .map_err(|_| anyhow!("event stream await timeout"))??;
+
+        let mut block: BlockchainUpdateInfo = match event {
+            Some(event) => Some(event).into(),
+            None => break,
+        };
+
+        let height = block.height.unwrap() as i32;
+
+        if height > to_height {
+            break;
+        }
+
+        if block.block_type != BlockType::Block || height != next_height {
+            return Err(anyhow!(
+                "unexpected {} at height {}, expected block at height {}",
+                block.block_type,
+                height,
+                next_height
+            ));
+        }
+
+        let (block_uid, block_id) = blocks_microblocks::get_solidified_block(&db, height)
+            .await?
+            .ok_or_else(|| anyhow!("no solidified block at height {}", height))?;
+
+        if block.id.as_ref() != Some(&block_id) {
+            return Err(anyhow!(
+                "block id at height {} differs: stored {}, received {}",
+                height,
+                block_id,
+                block.id.unwrap()
+            ));
+        }
+
+        block.uid = Some(block_uid);
+
+        chunks.iter_mut().for_each(|c| c.process(&block));
+        heights.push(height);
+        next_height = height + 1;
+
+        if heights.len() >= BATCH_MAX_BLOCKS {
+            replace_batch(&mut db, &heights, &mut chunks).await?;
+            heights.clear();
+        }
+    }
+
+    if !heights.is_empty() {
+        replace_batch(&mut db, &heights, &mut chunks).await?;
+    }
+
+    if next_height <= to_height {
+        return Err(anyhow!(
+            "event stream ended at height {} before {}",
+            next_height - 1,
+            to_height
+        ));
+    }
+
+    info!(
+        "reindex of heights {}..={} finished",
+        from_height, to_height
+    );
+
+    Ok(())
+}
+
+// the consumer may have moved safe heights down since the start (rollback, restart),
+// so they are checked again and locked until the batch is replaced
+async fn replace_batch(db: &mut Db, heights: &[i32], chunks: &mut [Box<dyn Chunk>]) -> Result<()> {
+    let tr = db.transaction().await?;
+
+    let safe_height = safe_heights::lock_min(&tr).await?;
+    let last_height = *heights.last().unwrap();
+    if last_height > safe_height {
+        return Err(anyhow!(
+            "height {} is above the safe height {}, it may be changed by the consumer",
+            last_height,
+            safe_height
+        ));
+    }
+
+    for chunk in chunks.iter() {
+        for table in chunk.history_tables() {
+            let sql = format!(
+                "delete from {table} where block_uid in (select uid from blocks_microblocks where height = any($1))"
+            );
+            tr.execute(sql.as_str(), &[&heights]).await?;
+        }
+
+        chunk.save_rows(&tr).await?;
+    }
+
+    tr.commit().await?;
+
+    chunks.iter_mut().for_each(|c| {
+        c.saved();
+        c.clear();
+    });
+
+    info!(
+        "reindexed heights {}..={}",
+        heights.first().unwrap(),
+        heights.last().unwrap()
+    );
+
+    Ok(())
+}
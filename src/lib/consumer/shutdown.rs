@@ -0,0 +1,26 @@
+use tokio::select;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use wavesexchange_log::info;
+
+// true after SIGTERM or SIGINT
+pub fn listen() -> watch::Receiver<bool> {
+    let (tx, rx) = watch::channel(false);
+
+    tokio::spawn(async move {
+        let mut sigterm = signal(SignalKind::terminate()).expect("can't listen to SIGTERM");
+
+        select! {
+            _ = sigterm.recv() => info!("SIGTERM received, shutting down"),
+            _ = tokio::signal::ctrl_c() => info!("SIGINT received, shutting down"),
+        }
+
+        let _ = tx.send(true);
+    });
+
+    rx
+}
+
+pub fn is_requested(shutdown: &watch::Receiver<bool>) -> bool {
+    *shutdown.borrow()
+}
@@ -0,0 +1,26 @@
+use crate::db::Db;
+
+// keys of session level advisory locks, small enough to be found in pg_locks by objid
+pub const CONSUMER_LOCK_KEY: i64 = 20221001;
+pub const DISTRIBUTION_LOCK_KEY: i64 = 20221002;
+pub const REINDEX_LOCK_KEY: i64 = 20221003;
+
+// lock is held until the connection is closed
+pub async fn try_lock(db: &Db, key: i64) -> Result<bool, anyhow::Error> {
+    let rows = db.query("select pg_try_advisory_lock($1)", &[&key]).await?;
+
+    Ok(rows[0].get(0))
+}
+
+pub async fn holder_pid(db: &Db, key: i64) -> Result<Option<i32>, anyhow::Error> {
+    let sql = "select pid from pg_locks where locktype = 'advisory' and granted and classid = 0 and objid::bigint = $1 and objsubid = 1";
+
+    let pid = db
+        .query(sql, &[&key])
+        .await?
+        .iter()
+        .map(|r| r.get(0))
+        .next();
+
+    Ok(pid)
+}
@@ -70,12 +70,12 @@ pub async fn process_task(
     let sql = "
         create table distribution_hist as
 
-        select bh.address_id, max(bh.uid) max_bh_uid
+        select distinct on (bh.address_id) bh.address_id, bh.uid max_bh_uid
             from balance_history bh 
             inner join blocks_microblocks b on bh.block_uid = b.uid
             where bh.asset_id = $1
             and b.height <= $2
-            group by address_id";
+            order by bh.address_id, bh.block_uid desc, bh.uid desc";
 
     info!("distribution task: create table distribution_hist ... ");
     tr.query(sql.into(), &[&task.asset_uid, &task.height])
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use crate::waves::bu::balance_updates::AssetHistory;
+use rust_decimal::Decimal;
+use tokio_postgres::Transaction;
+
+pub async fn save_bulk(
+    tr: &Transaction<'_>,
+    assets: &Vec<AssetHistory>,
+    asset_ids: &HashMap<String, i64>,
+) -> Result<Vec<i64>, anyhow::Error> {
+    if assets.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let block_uids: Vec<i64> = assets.iter().map(|a| a.block_uid).collect();
+    let asset_uids: Vec<i64> = assets
+        .iter()
+        .map(|a| {
+            *asset_ids
+                .get(&a.asset_id)
+                .expect(format!("asset_id: {} not found in map", &a.asset_id).as_str())
+        })
+        .collect();
+    let quantities: Vec<Decimal> = assets.iter().map(|a| a.quantity).collect();
+    let decimals: Vec<i32> = assets.iter().map(|a| a.decimals).collect();
+    let names: Vec<&str> = assets.iter().map(|a| a.name.as_str()).collect();
+    let reissuable: Vec<bool> = assets.iter().map(|a| a.reissuable).collect();
+    let scripts: Vec<Option<&[u8]>> = assets.iter().map(|a| a.script.as_deref()).collect();
+    let tx_ids: Vec<Option<&str>> = assets.iter().map(|a| a.tx_id.as_deref()).collect();
+
+    let sql = "insert into asset_history(block_uid, asset_id, quantity, decimals, name, reissuable, script, tx_id)
+                    select bm.uid, v.asset_id, v.quantity, v.decimals, v.name, v.reissuable, v.script, v.tx_id
+                        from unnest($1::BIGINT[], $2::BIGINT[], $3::NUMERIC[], $4::INTEGER[], $5::TEXT[], $6::BOOLEAN[], $7::BYTEA[], $8::TEXT[])
+                            with ordinality as v(block_uid, asset_id, quantity, decimals, name, reissuable, script, tx_id, chunk_uid)
+                        inner join blocks_microblocks bm on bm.uid = v.block_uid
+                        order by v.chunk_uid
+                    for update of bm
+                returning uid";
+
+    let ah_uids = tr
+        .query(
+            sql,
+            &[
+                &block_uids,
+                &asset_uids,
+                &quantities,
+                &decimals,
+                &names,
+                &reissuable,
+                &scripts,
+                &tx_ids,
+            ],
+        )
+        .await?
+        .iter()
+        .map(|r| r.get::<usize, i64>(0))
+        .collect();
+
+    Ok(ah_uids)
+}
@@ -0,0 +1,42 @@
+use crate::waves::bu::continuity::Discrepancy;
+use rust_decimal::Decimal;
+use tokio_postgres::Transaction;
+
+pub async fn save_bulk(
+    tr: &Transaction<'_>,
+    discrepancies: &[Discrepancy],
+) -> Result<u64, anyhow::Error> {
+    if discrepancies.is_empty() {
+        return Ok(0);
+    }
+
+    let block_uids: Vec<i64> = discrepancies.iter().map(|d| d.block_uid).collect();
+    let address_ids: Vec<i64> = discrepancies.iter().map(|d| d.address_id).collect();
+    let asset_ids: Vec<i64> = discrepancies.iter().map(|d| d.asset_id).collect();
+    let expected: Vec<Decimal> = discrepancies.iter().map(|d| d.expected).collect();
+    let amounts_before: Vec<Decimal> = discrepancies.iter().map(|d| d.amount_before).collect();
+    let tx_ids: Vec<Option<&str>> = discrepancies.iter().map(|d| d.tx_id.as_deref()).collect();
+
+    let sql = "insert into balance_discrepancies(block_uid, address_id, asset_id, expected_amount, amount_before, tx_id)
+                    select bm.uid, v.address_id, v.asset_id, v.expected_amount, v.amount_before, v.tx_id
+                        from unnest($1::BIGINT[], $2::BIGINT[], $3::BIGINT[], $4::NUMERIC[], $5::NUMERIC[], $6::TEXT[])
+                            as v(block_uid, address_id, asset_id, expected_amount, amount_before, tx_id)
+                        inner join blocks_microblocks bm on bm.uid = v.block_uid
+                    for update";
+
+    let saved = tr
+        .execute(
+            sql,
+            &[
+                &block_uids,
+                &address_ids,
+                &asset_ids,
+                &expected,
+                &amounts_before,
+                &tx_ids,
+            ],
+        )
+        .await?;
+
+    Ok(saved)
+}
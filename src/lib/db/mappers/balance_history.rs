@@ -1,8 +1,9 @@
 use std::collections::HashMap;
 
 use crate::waves::bu::balance_updates::BalanceHistory;
+use futures::pin_mut;
 use rust_decimal::Decimal;
-use tokio_postgres::Transaction;
+use tokio_postgres::{binary_copy::BinaryCopyInWriter, types::Type, Transaction};
 
 #[derive(Clone, Debug)]
 pub struct RowBalanceHistory {
@@ -10,10 +11,22 @@ pub struct RowBalanceHistory {
     pub address_id: i64,
     pub asset_id: i64,
     pub amount: Decimal,
+    pub amount_before: Decimal,
     pub block_height: u32,
+    pub tx_id: Option<String>,
 }
 
-const BULK_CHUNK_SIZE: usize = 5000;
+// per connection staging table for COPY, rows are dropped on commit
+const CREATE_STAGING_SQL: &str = "create temp table if not exists balance_history_staging (
+                    chunk_uid BIGINT NOT NULL,
+                    block_uid BIGINT NOT NULL,
+                    amount numeric(100),
+                    amount_before numeric(100),
+                    delta numeric(100),
+                    address_id BIGINT NOT NULL,
+                    asset_id BIGINT NOT NULL,
+                    tx_id TEXT
+                ) on commit delete rows";
 
 pub async fn save_bulk(
     tr: &Transaction<'_>,
@@ -21,69 +34,113 @@ pub async fn save_bulk(
     asset_ids: &HashMap<String, i64>,
     address_ids: &HashMap<String, i64>,
 ) -> Result<Vec<i64>, anyhow::Error> {
-    let mut bh_uids = vec![];
-
-    for ch in balances.chunks(BULK_CHUNK_SIZE).into_iter() {
-        let mut vals = "".to_owned();
-        // let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(BULK_CHUNK_SIZE);
-
-        ch.into_iter().enumerate().for_each(|(idx, b)| {
-            let address_id = address_ids
-                .get(&b.address)
-                .expect("address not found in map");
-
-            let asset_id = asset_ids
-                .get(&b.asset_id)
-                .expect(format!("asset_id: {} not found in map", &b.asset_id).as_str());
-
-            // непонятно почему но биндинг с таким запросом не работает поэтому
-            // запихнём числа в запрос у нас всё безопастно так как всё int
-            vals.push_str(
-                format!(
-                    " ({},{},{},{},{}),",
-                    idx + 1,
-                    b.block_uid,
-                    b.amount,
-                    address_id,
-                    asset_id,
-                )
-                .as_str(),
-            );
-        });
-
-        let to_trim: &[_] = &[',', ' '];
-        let vals = vals.trim_end_matches(to_trim).to_owned();
-
-        // так как в balances записи могут быть из разных блоков/микроблоков, то в нём могут быть записи которые откатились
-        // через blockchain-rollback и мы получим ошибку constraint на таблицу blocks_microblocks
-        // поэтому перед вставкой мы проверяем есть ли такой uid в blocks_microblocks
-        // внутренний select должет пытаться взять блокировку blocks_microblocks по uid, так как
-        // в соседнем соединении после начала нашей транзакции может прийти
-        // blockchain-rollback а мы вставляем уже несуществующий block_uid
-        // chunk_uid нужен для того что бы сохранять порядок вставляемых записей с порядком следования в массиве chunk
-
-        let sql = format!("insert into balance_history(block_uid, amount, address_id, asset_id) 
-                                    select bm.uid, vals.amount, vals.address_id, vals.asset_id
-                                        from (values {vals}) as vals(chunk_uid, block_uid, amount, address_id, asset_id)
-                                        inner join blocks_microblocks bm on bm.uid = vals.block_uid
-                                        order by chunk_uid
-                                    for update
-                                returning uid");
-
-        // println!("sql: {};", sql);
-        // println!("params: {:#?}; ", &params);
-
-        let st = tr.prepare(&sql).await?;
-
-        let mut inserted_bh_uids: Vec<i64> = tr
-            .query(&st, &[])
-            .await?
-            .iter()
-            .map(|r| r.get::<usize, i64>(0))
-            .collect();
-
-        bh_uids.append(&mut inserted_bh_uids);
+    if balances.is_empty() {
+        return Ok(vec![]);
     }
 
+    tr.batch_execute(CREATE_STAGING_SQL).await?;
+
+    let sink = tr
+        .copy_in("copy balance_history_staging(chunk_uid, block_uid, amount, amount_before, delta, address_id, asset_id, tx_id) from stdin binary")
+        .await?;
+
+    let writer = BinaryCopyInWriter::new(
+        sink,
+        &[
+            Type::INT8,
+            Type::INT8,
+            Type::NUMERIC,
+            Type::NUMERIC,
+            Type::NUMERIC,
+            Type::INT8,
+            Type::INT8,
+            Type::TEXT,
+        ],
+    );
+    pin_mut!(writer);
+
+    for (idx, b) in balances.iter().enumerate() {
+        let address_id = address_ids
+            .get(&b.address)
+            .expect("address not found in map");
+
+        let asset_id = asset_ids
+            .get(&b.asset_id)
+            .expect(format!("asset_id: {} not found in map", &b.asset_id).as_str());
+
+        let delta = b.amount - b.amount_before;
+
+        writer
+            .as_mut()
+            .write(&[
+                &(idx as i64),
+                &b.block_uid,
+                &b.amount,
+                &b.amount_before,
+                &delta,
+                address_id,
+                asset_id,
+                &b.tx_id,
+            ])
+            .await?;
+    }
+
+    writer.finish().await?;
+
+    // staged rows of blocks removed by a rollback are skipped by the join,
+    // for update of bm keeps a concurrent rollback from deleting the blocks until commit;
+    // chunk_uid keeps the order of the chunk
+    let sql = "insert into balance_history(block_uid, amount, amount_before, delta, address_id, asset_id, tx_id)
+                    select bm.uid, s.amount, s.amount_before, s.delta, s.address_id, s.asset_id, s.tx_id
+                        from balance_history_staging s
+                        inner join blocks_microblocks bm on bm.uid = s.block_uid
+                        order by s.chunk_uid
+                    for update of bm
+                returning uid";
+
+    let bh_uids = tr
+        .query(sql, &[])
+        .await?
+        .iter()
+        .map(|r| r.get::<usize, i64>(0))
+        .collect();
+
+    // save_bulk may be called more than once in a transaction
+    tr.batch_execute("truncate balance_history_staging").await?;
+
     Ok(bh_uids)
 }
+
+// last stored amount of (address_id, asset_id) pairs
+pub async fn last_amounts(
+    tr: &Transaction<'_>,
+    pairs: &[(i64, i64)],
+) -> Result<HashMap<(i64, i64), Decimal>, anyhow::Error> {
+    if pairs.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let address_ids: Vec<i64> = pairs.iter().map(|p| p.0).collect();
+    let asset_ids: Vec<i64> = pairs.iter().map(|p| p.1).collect();
+
+    let sql = "select v.address_id, v.asset_id, bh.amount
+            from unnest($1::BIGINT[], $2::BIGINT[]) as v(address_id, asset_id)
+                cross join lateral (
+                    select amount from balance_history
+                        where address_id = v.address_id and asset_id = v.asset_id
+                        order by block_uid desc, uid desc
+                        limit 1
+                ) bh";
+
+    let amounts = tr
+        .query(sql, &[&address_ids, &asset_ids])
+        .await?
+        .iter()
+        .filter_map(|r| {
+            let amount: Option<Decimal> = r.get(2);
+            amount.map(|a| ((r.get(0), r.get(1)), a))
+        })
+        .collect();
+
+    Ok(amounts)
+}
@@ -0,0 +1,73 @@
+use crate::waves::bu::balance_updates::BalanceHistory;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use tokio_postgres::Transaction;
+
+// copies balance rows of blocks above block_uid before they are deleted by rollback;
+// rows not saved yet are archived by the consumer with save_bulk.
+// blocks deleted on resume or restart (not solidified tail, blocks above the safe height)
+// are ingested again from the stream, they aren't rollbacks and aren't archived
+pub async fn archive(
+    tr: &Transaction<'_>,
+    rollback_uid: i64,
+    block_uid: i64,
+) -> Result<u64, anyhow::Error> {
+    let sql = "insert into balance_history_rollbacks(rollback_uid, bh_uid, block_uid, block_id, height, address_id, asset_id, amount, amount_before, delta, tx_id)
+                    select $1, bh.uid, bh.block_uid, bm.id, bm.height, bh.address_id, bh.asset_id, bh.amount, bh.amount_before, bh.delta, bh.tx_id
+                        from balance_history bh
+                        inner join blocks_microblocks bm on bm.uid = bh.block_uid
+                    where bh.block_uid > $2
+                    order by bh.block_uid, bh.uid";
+
+    Ok(tr.execute(sql, &[&rollback_uid, &block_uid]).await?)
+}
+
+// rows of rolled back blocks dropped by the consumer before they were saved
+pub async fn save_bulk(
+    tr: &Transaction<'_>,
+    rows: &[(i64, BalanceHistory)],
+    asset_ids: &HashMap<String, i64>,
+    address_ids: &HashMap<String, i64>,
+) -> Result<u64, anyhow::Error> {
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let rollback_uids: Vec<i64> = rows.iter().map(|(uid, _)| *uid).collect();
+    let block_uids: Vec<i64> = rows.iter().map(|(_, b)| b.block_uid).collect();
+    let heights: Vec<i32> = rows.iter().map(|(_, b)| b.block_height as i32).collect();
+    let address_uids: Vec<i64> = rows.iter().map(|(_, b)| address_ids[&b.address]).collect();
+    let asset_uids: Vec<i64> = rows.iter().map(|(_, b)| asset_ids[&b.asset_id]).collect();
+    let amounts: Vec<Decimal> = rows.iter().map(|(_, b)| b.amount).collect();
+    let amounts_before: Vec<Decimal> = rows.iter().map(|(_, b)| b.amount_before).collect();
+    let deltas: Vec<Decimal> = rows
+        .iter()
+        .map(|(_, b)| b.amount - b.amount_before)
+        .collect();
+    let tx_ids: Vec<Option<&str>> = rows.iter().map(|(_, b)| b.tx_id.as_deref()).collect();
+
+    let sql = "insert into balance_history_rollbacks(rollback_uid, block_uid, height, address_id, asset_id, amount, amount_before, delta, tx_id)
+                    select v.rollback_uid, v.block_uid, v.height, v.address_id, v.asset_id, v.amount, v.amount_before, v.delta, v.tx_id
+                        from unnest($1::BIGINT[], $2::BIGINT[], $3::INTEGER[], $4::BIGINT[], $5::BIGINT[], $6::NUMERIC[], $7::NUMERIC[], $8::NUMERIC[], $9::TEXT[])
+                            with ordinality as v(rollback_uid, block_uid, height, address_id, asset_id, amount, amount_before, delta, tx_id, idx)
+                    order by v.idx";
+
+    let saved = tr
+        .execute(
+            sql,
+            &[
+                &rollback_uids,
+                &block_uids,
+                &heights,
+                &address_uids,
+                &asset_uids,
+                &amounts,
+                &amounts_before,
+                &deltas,
+                &tx_ids,
+            ],
+        )
+        .await?;
+
+    Ok(saved)
+}
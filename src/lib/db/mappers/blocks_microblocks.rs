@@ -10,14 +10,18 @@ pub struct Block {
 }
 
 // this function called only once (per consumer restart) when we get first microblock after series of blocks; there is no way find out when we go to top of BU
-pub async fn unsolidify_last_block(tr: &Transaction<'_>) {
+pub async fn unsolidify_last_block(tr: &Transaction<'_>) -> Result<(), anyhow::Error> {
     let sql = "update blocks_microblocks set is_solidified = false where uid = (select uid from blocks_microblocks order by uid desc limit 1) returning uid";
 
-    let st = tr.prepare(&sql).await.unwrap();
-    let rows = tr.query(&st, &[]).await.unwrap();
-    let fix_uid: i64 = rows[0].get(0);
+    let st = tr.prepare(&sql).await?;
+    let rows = tr.query(&st, &[]).await?;
 
-    info!("fix solidify block_uid: {}; to false", fix_uid);
+    if let Some(row) = rows.first() {
+        let fix_uid: i64 = row.get(0);
+        info!("fix solidify block_uid: {}; to false", fix_uid);
+    }
+
+    Ok(())
 }
 
 pub async fn save(
@@ -27,10 +31,10 @@ pub async fn save(
     time_stamp: &i64,
     solidified: bool,
     block_type: &BlockType,
-) -> i64 {
+) -> Result<i64, anyhow::Error> {
     let sql = "insert into blocks_microblocks(id, height, time_stamp, is_solidified, block_type) values ($1,$2,$3,$4,$5) returning uid";
 
-    let st = tr.prepare(&sql).await.unwrap();
+    let st = tr.prepare(&sql).await?;
     let rows = tr
         .query(
             &st,
@@ -42,10 +46,9 @@ pub async fn save(
                 &block_type,
             ],
         )
-        .await
-        .unwrap();
+        .await?;
 
-    rows[0].get(0)
+    Ok(rows[0].get(0))
 }
 
 pub async fn get_last_height(db: &Db) -> Option<i32> {
@@ -70,38 +73,143 @@ pub async fn get_last_height(db: &Db) -> Option<i32> {
     }
 }
 
-pub async fn rollback(tr: &Transaction<'_>, block_id: &String) -> i64 {
-    let sql = "delete from blocks_microblocks where uid > (select max(uid) from blocks_microblocks where id = $1) returning uid, id, time_stamp, height, is_solidified";
+// returns deleted uids
+pub async fn delete_unsolidified(tr: &Transaction<'_>) -> Result<Vec<i64>, anyhow::Error> {
+    let sql = "delete from blocks_microblocks where is_solidified = false returning uid";
+
+    let uids = tr
+        .query(sql, &[])
+        .await?
+        .iter()
+        .map(|r| r.get::<usize, i64>(0))
+        .collect();
+
+    Ok(uids)
+}
+
+pub async fn get_max_uid(db: &Db) -> Result<i64, anyhow::Error> {
+    let sql = "select coalesce(max(uid), 0) from blocks_microblocks";
+
+    let rows = db.query(sql, &[]).await?;
+
+    Ok(rows[0].get(0))
+}
+
+// uid of the key block row and block id at the height, microblocks merged into it have greater uids
+pub async fn get_solidified_block(
+    db: &Db,
+    height: i32,
+) -> Result<Option<(i64, String)>, anyhow::Error> {
+    let sql = "select uid, id from blocks_microblocks where height = $1 and is_solidified = true order by uid limit 1";
+
+    let block = db
+        .query(sql, &[&height])
+        .await?
+        .iter()
+        .map(|r| (r.get(0), r.get(1)))
+        .next();
+
+    Ok(block)
+}
+
+// solidified blocks with uids set by the caller (parallel backfill), (uid, id, height, time_stamp)
+pub async fn save_solidified_bulk(
+    tr: &Transaction<'_>,
+    blocks: &[(i64, String, i32, i64)],
+) -> Result<(), anyhow::Error> {
+    let uids: Vec<i64> = blocks.iter().map(|b| b.0).collect();
+    let ids: Vec<&str> = blocks.iter().map(|b| b.1.as_str()).collect();
+    let heights: Vec<i32> = blocks.iter().map(|b| b.2).collect();
+    let time_stamps: Vec<i64> = blocks.iter().map(|b| b.3).collect();
+
+    let sql = "insert into blocks_microblocks(uid, id, height, time_stamp, is_solidified, block_type)
+                    select uid, id, height, time_stamp, true, $5
+                        from unnest($1::BIGINT[], $2::TEXT[], $3::INTEGER[], $4::BIGINT[]) as t(uid, id, height, time_stamp)";
+
+    tr.execute(
+        sql,
+        &[&uids, &ids, &heights, &time_stamps, &BlockType::Block],
+    )
+    .await?;
+
+    Ok(())
+}
+
+// uids for blocks saved later by save_solidified_bulk, in increasing order
+pub async fn reserve_uids(db: &Db, count: i32) -> Result<Vec<i64>, anyhow::Error> {
+    let sql = "select nextval(pg_get_serial_sequence('blocks_microblocks', 'uid')) from generate_series(1, $1) order by 1";
+
+    let uids = db
+        .query(sql, &[&count])
+        .await?
+        .iter()
+        .map(|r| r.get(0))
+        .collect();
+
+    Ok(uids)
+}
+
+// explicitly set uids don't move the identity sequence
+pub async fn reset_uid_sequence(tr: &Transaction<'_>) -> Result<(), anyhow::Error> {
+    let sql = "select setval(pg_get_serial_sequence('blocks_microblocks', 'uid'), coalesce(max(uid), 0) + 1, false) from blocks_microblocks";
+
+    tr.query(sql, &[]).await?;
+
+    Ok(())
+}
+
+// id of the last block or microblock (total block id for microblocks)
+pub async fn get_last_id(tr: &Transaction<'_>) -> Result<Option<String>, anyhow::Error> {
+    let sql = "select id from blocks_microblocks order by uid desc limit 1";
+
+    let id = tr.query(sql, &[]).await?.iter().map(|r| r.get(0)).next();
+
+    Ok(id)
+}
+
+pub async fn solidify_all(tr: &Transaction<'_>) -> Result<u64, anyhow::Error> {
+    let sql = "update blocks_microblocks set is_solidified = true where is_solidified = false";
+
+    Ok(tr.execute(sql, &[]).await?)
+}
 
-    let st = tr.prepare(&sql).await.unwrap();
+// returns uid of the last block left and uid of saved rollback
+pub async fn rollback(
+    tr: &Transaction<'_>,
+    block_id: &String,
+) -> Result<(i64, i64), anyhow::Error> {
+    let sql = "select max(uid) from blocks_microblocks where id = $1";
+
+    let st = tr.prepare(&sql).await?;
+    let target_uid: Option<i64> = tr.query_one(&st, &[&block_id]).await?.get(0);
+
+    let sql = "select uid, id, time_stamp, height, is_solidified from blocks_microblocks where uid > $1 order by uid for update";
+
+    let st = tr.prepare(&sql).await?;
     let mut del_rows: Vec<String> = vec![];
 
     let mut max_time_stamp: i64 = 0;
     let mut max_height: i32 = 0;
     let mut max_uid: i64 = 0;
 
-    tr.query(&st, &[&block_id])
-        .await
-        .unwrap()
-        .iter()
-        .for_each(|r| {
-            if r.get::<usize, i64>(0) > max_uid {
-                // as rollback values we use  values from last deleted block because rollback block do not all values
-                max_time_stamp = r.get(2);
-                max_uid = r.get::<usize, i64>(0);
-                max_height = r.get::<usize, i32>(3);
-            }
-            del_rows.push(format!(
-                "{},{},{},{},{}",
-                r.get::<usize, i64>(0),
-                r.get::<usize, String>(1),
-                r.get::<usize, i64>(2),
-                r.get::<usize, i32>(3),
-                r.get::<usize, bool>(4)
-            ));
-        });
-
-    save_rollback_info(
+    tr.query(&st, &[&target_uid]).await?.iter().for_each(|r| {
+        if r.get::<usize, i64>(0) > max_uid {
+            // as rollback values we use  values from last deleted block because rollback block do not all values
+            max_time_stamp = r.get(2);
+            max_uid = r.get::<usize, i64>(0);
+            max_height = r.get::<usize, i32>(3);
+        }
+        del_rows.push(format!(
+            "{},{},{},{},{}",
+            r.get::<usize, i64>(0),
+            r.get::<usize, String>(1),
+            r.get::<usize, i64>(2),
+            r.get::<usize, i32>(3),
+            r.get::<usize, bool>(4)
+        ));
+    });
+
+    let rollback_uid = save_rollback_info(
         &tr,
         &max_uid,
         &block_id,
@@ -109,16 +217,28 @@ pub async fn rollback(tr: &Transaction<'_>, block_id: &String) -> i64 {
         &max_time_stamp,
         &del_rows.join("\n"),
     )
-    .await;
+    .await?;
+
+    // rows are found only after an existing block
+    if let (Some(target_uid), false) = (target_uid, del_rows.is_empty()) {
+        // balance rows are removed by cascade, keep them for audit
+        mappers::balance_history_rollbacks::archive(tr, rollback_uid, target_uid).await?;
+
+        let sql = "delete from blocks_microblocks where uid > $1";
+
+        let st = tr.prepare(&sql).await?;
+        tr.execute(&st, &[&target_uid]).await?;
+    }
 
     let sql = "select uid from blocks_microblocks order by uid desc limit 1";
 
-    let st = tr.prepare(&sql).await.unwrap();
-    let rows = tr.query(&st, &[]).await.unwrap();
+    let st = tr.prepare(&sql).await?;
+    let row = tr.query_one(&st, &[]).await?;
 
-    rows[0].get(0)
+    Ok((row.get(0), rollback_uid))
 }
 
+// returns uid of saved rollback
 pub async fn save_rollback_info(
     tr: &Transaction<'_>,
     max_uid: &i64,
@@ -126,26 +246,31 @@ pub async fn save_rollback_info(
     max_height: &i32,
     max_time_stamp: &i64,
     deleted_blocks_data: &String,
-) {
-    let sql = "insert into blocks_rollbacks(max_uid, id, max_height, max_time_stamp, deleted_blocks_data) values ($1,$2,$3,$4,$5)";
-
-    let st = tr.prepare(&sql).await.unwrap();
-    tr.query(
-        &st,
-        &[
-            &max_uid,
-            &block_id,
-            &max_height,
-            &max_time_stamp,
-            &deleted_blocks_data,
-        ],
-    )
-    .await
-    .unwrap();
+) -> Result<i64, anyhow::Error> {
+    let sql = "insert into blocks_rollbacks(max_uid, id, max_height, max_time_stamp, deleted_blocks_data) values ($1,$2,$3,$4,$5) returning uid";
+
+    let st = tr.prepare(&sql).await?;
+    let row = tr
+        .query_one(
+            &st,
+            &[
+                &max_uid,
+                &block_id,
+                &max_height,
+                &max_time_stamp,
+                &deleted_blocks_data,
+            ],
+        )
+        .await?;
+
+    Ok(row.get(0))
 }
 
 // return (max(uid), height, time_stamp)
-pub async fn solidify(tr: &Transaction<'_>, ref_block_id: &String) -> Option<(i64, i32, i64)> {
+pub async fn solidify(
+    tr: &Transaction<'_>,
+    ref_block_id: &String,
+) -> Result<Option<(i64, i32, i64)>, anyhow::Error> {
     let sql = r#"
         with last_real_block as  (
                     update blocks_microblocks set is_solidified = true, microblock_id = id, id = $1 where time_stamp <> 0 and is_solidified = false returning uid, id, time_stamp, height
@@ -164,8 +289,8 @@ pub async fn solidify(tr: &Transaction<'_>, ref_block_id: &String) -> Option<(i6
                 select max(uid), max(height), max(time_stamp) from upd
         "#;
 
-    let st = tr.prepare(&sql).await.unwrap();
-    let rows = tr.query(&st, &[&ref_block_id]).await.unwrap();
+    let st = tr.prepare(&sql).await?;
+    let rows = tr.query(&st, &[&ref_block_id]).await?;
 
     info!("solidify: ref_block_id: {};", ref_block_id);
 
@@ -177,8 +302,8 @@ pub async fn solidify(tr: &Transaction<'_>, ref_block_id: &String) -> Option<(i6
 
         if uid.is_ok() {
             // непонятно но видимо иногда приходит rollback на id которого никогда не было
-            return Some((uid.unwrap(), height.unwrap(), timestamp.unwrap()));
+            return Ok(Some((uid.unwrap(), height.unwrap(), timestamp.unwrap())));
         }
     }
-    None
+    Ok(None)
 }
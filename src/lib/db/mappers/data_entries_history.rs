@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use crate::waves::bu::data_entries::{DataEntryHistory, DataValue};
+use tokio_postgres::Transaction;
+
+pub async fn save_bulk(
+    tr: &Transaction<'_>,
+    entries: &Vec<DataEntryHistory>,
+    address_ids: &HashMap<String, i64>,
+) -> Result<Vec<i64>, anyhow::Error> {
+    if entries.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let block_uids: Vec<i64> = entries.iter().map(|e| e.block_uid).collect();
+    let address_uids: Vec<i64> = entries
+        .iter()
+        .map(|e| {
+            *address_ids
+                .get(&e.address)
+                .expect(format!("address: {} not found in map", &e.address).as_str())
+        })
+        .collect();
+    let keys: Vec<&str> = entries.iter().map(|e| e.key.as_str()).collect();
+
+    let int_vals: Vec<Option<i64>> = entries
+        .iter()
+        .map(|e| match e.value {
+            Some(DataValue::Int(v)) => Some(v),
+            _ => None,
+        })
+        .collect();
+
+    let bool_vals: Vec<Option<bool>> = entries
+        .iter()
+        .map(|e| match e.value {
+            Some(DataValue::Bool(v)) => Some(v),
+            _ => None,
+        })
+        .collect();
+
+    let bin_vals: Vec<Option<&[u8]>> = entries
+        .iter()
+        .map(|e| match &e.value {
+            Some(DataValue::Binary(v)) => Some(v.as_slice()),
+            _ => None,
+        })
+        .collect();
+
+    let str_vals: Vec<Option<&str>> = entries
+        .iter()
+        .map(|e| match &e.value {
+            Some(DataValue::String(v)) => Some(v.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    let tx_ids: Vec<Option<&str>> = entries.iter().map(|e| e.tx_id.as_deref()).collect();
+
+    let sql = "insert into data_entries_history(block_uid, address_id, key, int_val, bool_val, bin_val, str_val, tx_id)
+                    select bm.uid, v.address_id, v.key, v.int_val, v.bool_val, v.bin_val, v.str_val, v.tx_id
+                        from unnest($1::BIGINT[], $2::BIGINT[], $3::TEXT[], $4::BIGINT[], $5::BOOLEAN[], $6::BYTEA[], $7::TEXT[], $8::TEXT[])
+                            with ordinality as v(block_uid, address_id, key, int_val, bool_val, bin_val, str_val, tx_id, chunk_uid)
+                        inner join blocks_microblocks bm on bm.uid = v.block_uid
+                        order by v.chunk_uid
+                    for update of bm
+                returning uid";
+
+    let deh_uids = tr
+        .query(
+            sql,
+            &[
+                &block_uids,
+                &address_uids,
+                &keys,
+                &int_vals,
+                &bool_vals,
+                &bin_vals,
+                &str_vals,
+                &tx_ids,
+            ],
+        )
+        .await?
+        .iter()
+        .map(|r| r.get::<usize, i64>(0))
+        .collect();
+
+    Ok(deh_uids)
+}
@@ -38,6 +38,28 @@ pub async fn next_task(db: &Db) -> Result<Option<AssetDistributionTask>, anyhow:
     Ok(row)
 }
 
+pub async fn count_queued(db: &Db) -> Result<i64, anyhow::Error> {
+    let sql = "select count(*) from asset_distribution_tasks where task_state = 'new'::enum_task_state_ad";
+
+    let rows = db.client.query(sql, &[]).await?;
+
+    Ok(rows[0].get(0))
+}
+
+pub async fn interrupt_in_progress(db: &Db) -> Result<(), anyhow::Error> {
+    let sql = "update asset_distribution_tasks set task_state='error', error_message='interrupted by shutdown', state_updated = now() where task_state='progress' returning uid";
+
+    db.client.query(sql, &[]).await?.iter().for_each(|r| {
+        let uid: i64 = r.get(0);
+        warn!(
+            "asset distribution task uid: {} interrupted by shutdown",
+            uid
+        );
+    });
+
+    Ok(())
+}
+
 pub async fn find_failed_tasks(db: &Db) -> Result<(), anyhow::Error> {
     let sql = "update asset_distribution_tasks set task_state='error', error_message='consumer restarted', state_updated = now() where task_state='progress' returning uid";
 
@@ -0,0 +1,15 @@
+use crate::db::Db;
+
+// (kind, mode, value) rows, see IngestFilter
+pub async fn get_all(db: &Db) -> Result<Vec<(String, String, String)>, anyhow::Error> {
+    let sql = "select kind, mode, value from ingest_filters order by uid";
+
+    let rows = db
+        .query(sql, &[])
+        .await?
+        .iter()
+        .map(|r| (r.get(0), r.get(1), r.get(2)))
+        .collect();
+
+    Ok(rows)
+}
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use crate::waves::bu::balance_updates::LeasingHistory;
+use rust_decimal::Decimal;
+use tokio_postgres::Transaction;
+
+pub async fn save_bulk(
+    tr: &Transaction<'_>,
+    leasing: &Vec<LeasingHistory>,
+    address_ids: &HashMap<String, i64>,
+) -> Result<Vec<i64>, anyhow::Error> {
+    if leasing.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let block_uids: Vec<i64> = leasing.iter().map(|l| l.block_uid).collect();
+    let address_uids: Vec<i64> = leasing
+        .iter()
+        .map(|l| {
+            *address_ids
+                .get(&l.address)
+                .expect("address not found in map")
+        })
+        .collect();
+    let lease_in: Vec<Decimal> = leasing.iter().map(|l| l.lease_in).collect();
+    let lease_out: Vec<Decimal> = leasing.iter().map(|l| l.lease_out).collect();
+    let tx_ids: Vec<Option<&str>> = leasing.iter().map(|l| l.tx_id.as_deref()).collect();
+
+    let sql = "insert into leasing_history(block_uid, address_id, lease_in, lease_out, tx_id)
+                    select bm.uid, v.address_id, v.lease_in, v.lease_out, v.tx_id
+                        from unnest($1::BIGINT[], $2::BIGINT[], $3::NUMERIC[], $4::NUMERIC[], $5::TEXT[])
+                            with ordinality as v(block_uid, address_id, lease_in, lease_out, tx_id, chunk_uid)
+                        inner join blocks_microblocks bm on bm.uid = v.block_uid
+                        order by v.chunk_uid
+                    for update of bm
+                returning uid";
+
+    let lh_uids = tr
+        .query(
+            sql,
+            &[&block_uids, &address_uids, &lease_in, &lease_out, &tx_ids],
+        )
+        .await?
+        .iter()
+        .map(|r| r.get::<usize, i64>(0))
+        .collect();
+
+    Ok(lh_uids)
+}
@@ -1,7 +1,14 @@
+pub mod advisory_locks;
 pub mod asset_distribution;
+pub mod asset_history;
+pub mod balance_discrepancies;
 pub mod balance_history;
+pub mod balance_history_rollbacks;
 pub mod blocks_microblocks;
+pub mod data_entries_history;
 pub mod distribution_task;
+pub mod ingest_filters;
+pub mod leasing_history;
 pub mod safe_heights;
 pub mod unique_address;
 pub mod unique_assets;
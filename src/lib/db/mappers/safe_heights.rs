@@ -1,3 +1,4 @@
+use crate::db::Db;
 use tokio_postgres::Transaction;
 use wavesexchange_log::info;
 
@@ -33,3 +34,43 @@ pub async fn save(
 
     Ok(())
 }
+
+// creates the row if absent, existing height is kept
+pub async fn init(db: &Db, table_name: &str, height: i32) -> Result<(), anyhow::Error> {
+    db.query(
+        "insert into safe_heights(table_name, height) values ($1, $2) on conflict(table_name) do nothing",
+        &[&table_name, &height],
+    )
+    .await?;
+
+    Ok(())
+}
+
+// exact height for all tables, used where no rollback is possible (backfill)
+pub async fn set_all(db: &Db, height: i32) -> Result<(), anyhow::Error> {
+    db.query("update safe_heights set height = $1", &[&height])
+        .await?;
+
+    Ok(())
+}
+
+// blocks up to this height are never rolled back or deleted on restart
+pub async fn get_min(db: &Db) -> Result<i32, anyhow::Error> {
+    let rows = db
+        .query("select coalesce(min(height), 0) from safe_heights", &[])
+        .await?;
+
+    Ok(rows[0].get(0))
+}
+
+// get_min inside a transaction which keeps safe heights from changing until it ends
+pub async fn lock_min(tr: &Transaction<'_>) -> Result<i32, anyhow::Error> {
+    tr.execute("lock table safe_heights in share mode", &[])
+        .await?;
+
+    let rows = tr
+        .query("select coalesce(min(height), 0) from safe_heights", &[])
+        .await?;
+
+    Ok(rows[0].get(0))
+}
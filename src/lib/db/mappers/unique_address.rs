@@ -1,4 +1,3 @@
-use crate::waves::bu::balance_updates::BalanceHistory;
 use itertools::Itertools;
 use std::collections::HashMap;
 use tokio_postgres::{types::ToSql, Transaction};
@@ -7,13 +6,10 @@ const BULK_CHUNK_SIZE: usize = 5000;
 
 pub async fn merge_bulk(
     tr: &Transaction<'_>,
-    bh: &Vec<BalanceHistory>,
+    addresses: &[&str],
 ) -> Result<HashMap<String, i64>, anyhow::Error> {
-    let distinct_address: Vec<&str> = bh
-        .into_iter()
-        .map(|b| b.address.as_ref())
-        .unique()
-        .collect();
+    // sorted so concurrent analyzers lock new rows in the same order
+    let distinct_address: Vec<&str> = addresses.iter().copied().sorted().dedup().collect();
 
     let mut address_uid_map: HashMap<String, i64> = HashMap::with_capacity(distinct_address.len());
 
@@ -55,5 +51,25 @@ pub async fn merge_bulk(
         });
     }
 
+    // rows inserted by a concurrent transaction that committed after the statement started
+    // are neither returned by insert nor visible to its select
+    let missing: Vec<&str> = distinct_address
+        .iter()
+        .copied()
+        .filter(|v| !address_uid_map.contains_key(*v))
+        .collect();
+
+    if !missing.is_empty() {
+        tr.query(
+            "select uid, address from unique_address where address = any($1)",
+            &[&missing],
+        )
+        .await?
+        .iter()
+        .for_each(|r| {
+            address_uid_map.insert(r.get(1), r.get(0));
+        });
+    }
+
     Ok(address_uid_map)
 }
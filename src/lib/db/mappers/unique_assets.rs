@@ -1,4 +1,3 @@
-use crate::waves::bu::balance_updates::BalanceHistory;
 use itertools::Itertools;
 use std::collections::{HashMap, HashSet};
 use tokio_postgres::{types::ToSql, Transaction};
@@ -8,13 +7,15 @@ const WAVES_ASSET_ID: i64 = 1;
 
 pub async fn merge_bulk(
     tr: &Transaction<'_>,
-    bh: &Vec<BalanceHistory>,
+    assets: &[&str],
 ) -> Result<HashMap<String, i64>, anyhow::Error> {
-    let distinct_assets: Vec<&str> = bh
-        .into_iter()
-        .filter(|b| !(b.asset_id.is_empty() || b.asset_id.eq(&"WAVES")))
-        .map(|b| b.asset_id.as_ref())
-        .unique()
+    let distinct_assets: Vec<&str> = assets
+        .iter()
+        .copied()
+        .filter(|a| !(a.is_empty() || a.eq(&"WAVES")))
+        // sorted so concurrent analyzers lock new rows in the same order
+        .sorted()
+        .dedup()
         .collect();
 
     let mut asset_uid_map: HashMap<String, i64> = HashMap::with_capacity(distinct_assets.len() + 2);
@@ -59,5 +60,25 @@ pub async fn merge_bulk(
         });
     }
 
+    // rows inserted by a concurrent transaction that committed after the statement started
+    // are neither returned by insert nor visible to its select
+    let missing: Vec<&str> = distinct_assets
+        .iter()
+        .copied()
+        .filter(|v| !asset_uid_map.contains_key(*v))
+        .collect();
+
+    if !missing.is_empty() {
+        tr.query(
+            "select uid, asset_id from unique_assets where asset_id = any($1)",
+            &[&missing],
+        )
+        .await?
+        .iter()
+        .for_each(|r| {
+            asset_uid_map.insert(r.get(1), r.get(0));
+        });
+    }
+
     Ok(asset_uid_map)
 }
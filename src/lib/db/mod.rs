@@ -5,7 +5,9 @@ use crate::config::postgres::PostgresConfig;
 use async_trait::async_trait;
 use deadpool_postgres::Pool;
 
-use tokio_postgres::{types::ToSql, Client as TokioPgClient, Error, Row, Statement, Transaction};
+use tokio_postgres::{
+    error::SqlState, types::ToSql, Client as TokioPgClient, Error, Row, Statement, Transaction,
+};
 
 pub type PooledDb = Pool;
 
@@ -41,3 +43,34 @@ impl Db {
         Ok(self.client.transaction().await?)
     }
 }
+
+// connection problems, serialization failures and deadlocks are worth to retry
+pub fn is_transient_error(err: &anyhow::Error) -> bool {
+    let pg_err = match err.downcast_ref::<Error>() {
+        Some(e) => e,
+        None => return false,
+    };
+
+    if pg_err.is_closed() {
+        return true;
+    }
+
+    match pg_err.code() {
+        Some(code) => {
+            // class 08 - connection exception
+            code.code().starts_with("08")
+                || [
+                    SqlState::T_R_SERIALIZATION_FAILURE,
+                    SqlState::T_R_DEADLOCK_DETECTED,
+                    SqlState::ADMIN_SHUTDOWN,
+                    SqlState::CRASH_SHUTDOWN,
+                    SqlState::CANNOT_CONNECT_NOW,
+                    SqlState::TOO_MANY_CONNECTIONS,
+                ]
+                .contains(code)
+        }
+        None => std::error::Error::source(pg_err)
+            .map(|e| e.is::<std::io::Error>())
+            .unwrap_or(false),
+    }
+}
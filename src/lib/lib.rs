@@ -4,4 +4,5 @@ pub mod api;
 pub mod config;
 pub mod consumer;
 pub mod db;
+pub mod verify;
 pub mod waves;
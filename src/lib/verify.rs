@@ -0,0 +1,217 @@
+use crate::api::{repo, BalanceEntry};
+use crate::config::verify::Config;
+use anyhow::{anyhow, Result};
+use deadpool_postgres::{ManagerConfig, Pool, RecyclingMethod, Runtime};
+use futures::future::try_join_all;
+use itertools::Itertools;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio_postgres::NoTls;
+use wavesexchange_log::info;
+
+// max addresses in one node request
+const NODE_REQUEST_MAX_IDS: usize = 100;
+const WAVES_ASSET_ID: &str = "WAVES";
+
+#[derive(Debug, Serialize)]
+pub struct Mismatch {
+    pub address: String,
+    pub asset_id: String,
+    // None if there is no row for the pair at the height
+    pub stored: Option<Decimal>,
+    pub node: Decimal,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub node_url: String,
+    pub height: u32,
+    pub checked: usize,
+    pub mismatches: Vec<Mismatch>,
+    pub errors: Vec<String>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty() && self.errors.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct NodeBalanceRequest<'a> {
+    ids: &'a [String],
+    height: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asset: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeBalance {
+    id: String,
+    balance: u64,
+}
+
+// compares balances of sampled address/asset pairs at the height with the node
+pub async fn run(config: &Config) -> Result<Report> {
+    let pool = create_pool(config)?;
+
+    let height = match config.height {
+        Some(h) => h,
+        None => repo::last_solidified_height(&pool).await?,
+    };
+
+    let mut params = HashMap::with_capacity(1);
+    params.insert("height".to_owned(), height.to_string());
+    let uid = repo::get_uids_from_req(&pool, &params).await?;
+
+    let pairs = sample_pairs(&pool, config.sample_size).await?;
+
+    info!(
+        "verify {} address/asset pairs at height {} against {}",
+        pairs.len(),
+        height,
+        config.node_url
+    );
+
+    let client = reqwest::Client::new();
+
+    let mut report = Report {
+        node_url: config.node_url.clone(),
+        height: height,
+        checked: 0,
+        mismatches: vec![],
+        errors: vec![],
+    };
+
+    let by_asset = pairs.into_iter().into_group_map_by(|p| p.asset_id.clone());
+
+    for (asset_id, entries) in by_asset {
+        for ch in entries.chunks(NODE_REQUEST_MAX_IDS) {
+            let addresses: Vec<String> = ch.iter().map(|e| e.address.clone()).collect();
+
+            let node_balances =
+                match node_balances(&client, &config.node_url, &addresses, height, &asset_id).await
+                {
+                    Ok(b) => b,
+                    Err(err) => {
+                        report.errors.push(format!(
+                            "asset_id: {}; node request failed: {}",
+                            asset_id, err
+                        ));
+                        continue;
+                    }
+                };
+
+            let stored =
+                try_join_all(ch.iter().map(|e| repo::balance_query(&pool, &uid, e))).await?;
+
+            for (entry, stored) in ch.iter().zip(stored) {
+                report.checked += 1;
+
+                let stored = stored.map(|s| s.amount);
+
+                match node_balances.get(&entry.address) {
+                    Some(node) if stored.unwrap_or(Decimal::ZERO) == *node => {}
+                    Some(node) => report.mismatches.push(Mismatch {
+                        address: entry.address.clone(),
+                        asset_id: entry.asset_id.clone(),
+                        stored: stored,
+                        node: *node,
+                    }),
+                    None => report.errors.push(format!(
+                        "address: {}; asset_id: {}; missing in node response",
+                        entry.address, entry.asset_id
+                    )),
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+fn create_pool(config: &Config) -> Result<Pool> {
+    let mut db_cfg = deadpool_postgres::Config::new();
+
+    db_cfg.host = Some(config.postgres.host.to_string());
+    db_cfg.port = Some(config.postgres.port);
+    db_cfg.user = Some(config.postgres.user.to_string());
+    db_cfg.password = Some(config.postgres.password.to_string());
+    db_cfg.dbname = Some(config.postgres.database.to_string());
+
+    db_cfg.connect_timeout = Some(std::time::Duration::from_secs(5));
+
+    db_cfg.manager = Some(ManagerConfig {
+        recycling_method: RecyclingMethod::Fast,
+    });
+
+    Ok(db_cfg.create_pool(Some(Runtime::Tokio1), NoTls)?)
+}
+
+// random rows of balance_history, cheap on a big table unlike order by random()
+async fn sample_pairs(pool: &Pool, sample_size: u32) -> Result<Vec<BalanceEntry>> {
+    let conn = pool.get().await?;
+
+    let max_uid: i64 = conn
+        .query_one("select coalesce(max(uid), 0) from balance_history", &[])
+        .await?
+        .get(0);
+
+    let sql = "select distinct ua.address, ast.asset_id
+            from (select (random() * $2)::BIGINT start_uid from generate_series(1, $1)) r
+                cross join lateral (
+                    select address_id, asset_id from balance_history where uid >= r.start_uid order by uid limit 1
+                ) bh
+                inner join unique_address ua on ua.uid = bh.address_id
+                inner join unique_assets ast on ast.uid = bh.asset_id";
+
+    let pairs = conn
+        .query(sql, &[&(sample_size as i32), &(max_uid as f64)])
+        .await?
+        .iter()
+        .map(|r| BalanceEntry {
+            address: r.get(0),
+            asset_id: r.get(1),
+        })
+        .collect();
+
+    Ok(pairs)
+}
+
+async fn node_balances(
+    client: &reqwest::Client,
+    node_url: &str,
+    addresses: &[String],
+    height: u32,
+    asset_id: &str,
+) -> Result<HashMap<String, Decimal>> {
+    let request = NodeBalanceRequest {
+        ids: addresses,
+        height: height,
+        asset: match asset_id {
+            WAVES_ASSET_ID | "" => None,
+            a => Some(a),
+        },
+    };
+
+    let resp = client
+        .post(format!(
+            "{}/addresses/balance",
+            node_url.trim_end_matches('/')
+        ))
+        .json(&request)
+        .send()
+        .await?;
+
+    if !resp.status().is_success() {
+        return Err(anyhow!("node responded with {}", resp.status()));
+    }
+
+    let balances: Vec<NodeBalance> = resp.json().await?;
+
+    Ok(balances
+        .into_iter()
+        .map(|b| (b.id, Decimal::from(b.balance)))
+        .collect())
+}
@@ -1,15 +1,20 @@
-use crate::consumer::SETTINGS;
-use crate::db::{
-    mappers::{balance_history, safe_heights, unique_address, unique_assets},
-    *,
+use super::chunk_analyzer::Chunk;
+use super::continuity::ContinuityCheck;
+use super::filters::IngestFilter;
+use super::id_cache::{self, IdCache};
+use crate::db::mappers::{
+    asset_history, balance_history, balance_history_rollbacks, leasing_history,
 };
 use crate::waves::{BlockType, BlockchainUpdateInfo};
+use async_trait::async_trait;
 use rust_decimal::Decimal;
-use tokio::sync::mpsc::{self, Sender};
-use waves_protobuf_schemas::waves::{events::state_update::BalanceUpdate, Amount};
-use wavesexchange_log::{error, info};
+use tokio_postgres::Transaction;
+use waves_protobuf_schemas::waves::{
+    events::state_update::{AssetStateUpdate, BalanceUpdate, LeasingUpdate},
+    Amount,
+};
+use wavesexchange_log::info;
 
-const CHUNK_SIZE: usize = 1000;
 const BH_TABLE_NAME: &str = "balance_history";
 
 #[derive(Debug)]
@@ -18,86 +23,242 @@ pub struct BalanceHistory {
     pub address: String,
     pub asset_id: String,
     pub amount: Decimal,
+    pub amount_before: Decimal,
+    pub block_height: u32,
+    // None for block level changes
+    pub tx_id: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct LeasingHistory {
+    pub block_uid: i64,
+    pub address: String,
+    pub lease_in: Decimal,
+    pub lease_out: Decimal,
+    pub block_height: u32,
+    pub tx_id: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct AssetHistory {
+    pub block_uid: i64,
+    pub asset_id: String,
+    pub quantity: Decimal,
+    pub decimals: i32,
+    pub name: String,
+    pub reissuable: bool,
+    pub script: Option<Vec<u8>>,
     pub block_height: u32,
+    pub tx_id: Option<String>,
 }
 
-pub struct Analyzer {
-    sender: Sender<BlockchainUpdateInfo>,
+// balance, leasing and asset changes saved in one transaction
+#[derive(Debug, Default)]
+pub struct BalanceChunk {
+    pub balances: Vec<BalanceHistory>,
+    pub leasing: Vec<LeasingHistory>,
+    pub assets: Vec<AssetHistory>,
+    // not saved balance rows of rolled back blocks by uid of the rollback, kept for audit
+    pub rolled_back: Vec<(i64, BalanceHistory)>,
+    filter: IngestFilter,
+    continuity: Option<ContinuityCheck>,
+    address_ids: IdCache,
+    asset_ids: IdCache,
 }
 
-impl Analyzer {
-    pub async fn new(buf_size: usize) -> Self {
-        let (tx, mut rx) = mpsc::channel::<BlockchainUpdateInfo>(buf_size);
+#[async_trait]
+impl Chunk for BalanceChunk {
+    fn name(&self) -> &'static str {
+        "balances"
+    }
+
+    fn safe_heights_table(&self) -> &'static str {
+        BH_TABLE_NAME
+    }
 
-        tokio::spawn(async move {
-            let mut db = Db::new(&SETTINGS.config.postgres).await.unwrap();
+    fn history_tables(&self) -> &'static [&'static str] {
+        &["balance_history", "leasing_history", "asset_history"]
+    }
 
-            let mut chunk: Vec<BalanceHistory> = Vec::with_capacity(CHUNK_SIZE);
+    fn process(&mut self, block: &BlockchainUpdateInfo) {
+        process(block, self);
+    }
 
-            let mut was_microblocks = false;
+    fn len(&self) -> usize {
+        self.balances.len() + self.leasing.len() + self.assets.len() + self.rolled_back.len()
+    }
 
-            while let Some(block) = rx.recv().await {
-                process(&block, &mut chunk);
+    fn clear(&mut self) {
+        self.balances.clear();
+        self.rolled_back.clear();
+        self.leasing.clear();
+        self.assets.clear();
+    }
 
-                if block.block_type == BlockType::MicroBlock {
-                    was_microblocks = true;
-                }
+    fn min_height(&self) -> Option<u32> {
+        let balances = self.balances.iter().map(|i| i.block_height);
+        let leasing = self.leasing.iter().map(|i| i.block_height);
+        let assets = self.assets.iter().map(|i| i.block_height);
 
-                if was_microblocks || chunk.len() >= CHUNK_SIZE {
-                    save_chunk(&mut db, &chunk).await.unwrap(); // не понимаю можно ли тут сделать что-то более полезное чем unwrap
-                    chunk.clear();
-                }
-            }
-        });
+        balances.chain(leasing).chain(assets).min()
+    }
+
+    async fn save_rows(&self, tr: &Transaction<'_>) -> Result<usize, anyhow::Error> {
+        save_rows(tr, self).await
+    }
+
+    fn saved(&mut self) {
+        self.address_ids.commit();
+        self.asset_ids.commit();
+
+        if let Some(continuity) = self.continuity.as_mut() {
+            continuity.commit();
+        }
+    }
+}
+
+impl BalanceChunk {
+    pub fn new(filter: IngestFilter) -> Self {
+        Self {
+            filter: filter,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_id_cache_size(mut self, size: usize) -> Self {
+        self.address_ids = IdCache::new(size);
+        self.asset_ids = IdCache::new(size);
+        self
+    }
+
+    // only valid when blocks are saved in order of heights by a single analyzer
+    pub fn with_continuity_check(mut self) -> Self {
+        self.continuity = Some(ContinuityCheck::default());
+        self
+    }
 
-        Self { sender: tx }
+    pub fn addresses(&self) -> Vec<&str> {
+        let balances = self.balances.iter().map(|i| i.address.as_str());
+        let leasing = self.leasing.iter().map(|i| i.address.as_str());
+        let rolled_back = self.rolled_back.iter().map(|(_, i)| i.address.as_str());
+
+        balances.chain(leasing).chain(rolled_back).collect()
     }
 
-    pub async fn send(&self, block: &BlockchainUpdateInfo) {
-        self.sender.send(block.clone()).await.expect("send failed");
+    pub fn assets(&self) -> Vec<&str> {
+        let balances = self.balances.iter().map(|i| i.asset_id.as_str());
+        let assets = self.assets.iter().map(|i| i.asset_id.as_str());
+        let rolled_back = self.rolled_back.iter().map(|(_, i)| i.asset_id.as_str());
+
+        balances.chain(assets).chain(rolled_back).collect()
     }
 }
 
-async fn save_chunk(db: &mut Db, chunk: &Vec<BalanceHistory>) -> Result<(), anyhow::Error> {
-    let tr = db.transaction().await?;
+async fn save_rows(tr: &Transaction<'_>, chunk: &BalanceChunk) -> Result<usize, anyhow::Error> {
+    let assets_map = id_cache::merge_assets(tr, &chunk.asset_ids, &chunk.assets()).await?;
+    let address_map = id_cache::merge_addresses(tr, &chunk.address_ids, &chunk.addresses()).await?;
+
+    // before the inserts: amounts stored before this chunk are compared
+    if let Some(continuity) = &chunk.continuity {
+        continuity
+            .check(tr, &chunk.balances, &assets_map, &address_map)
+            .await?;
+    }
 
-    let assets_map = unique_assets::merge_bulk(&tr, &chunk).await?;
-    let address_map = unique_address::merge_bulk(&tr, &chunk).await?;
+    let bh_uids =
+        balance_history::save_bulk(tr, &chunk.balances, &assets_map, &address_map).await?;
+    let lh_uids = leasing_history::save_bulk(tr, &chunk.leasing, &address_map).await?;
+    let ah_uids = asset_history::save_bulk(tr, &chunk.assets, &assets_map).await?;
+    let archived =
+        balance_history_rollbacks::save_bulk(tr, &chunk.rolled_back, &assets_map, &address_map)
+            .await?;
 
-    let bh_uids = balance_history::save_bulk(&tr, &chunk, &assets_map, &address_map).await?;
-    if !bh_uids.is_empty() {
-        let bh_min_height = chunk.iter().map(|i| i.block_height).min().unwrap_or(1);
-        info!("bulk saved balance_history records: {}", bh_uids.len());
-        safe_heights::save(&tr, BH_TABLE_NAME, bh_min_height - 1).await?;
+    if archived > 0 {
+        info!(
+            "archived not saved rolled back balance records: {}",
+            archived
+        );
     }
 
-    tr.commit().await?;
+    if !bh_uids.is_empty() || !lh_uids.is_empty() || !ah_uids.is_empty() {
+        info!(
+            "bulk saved balance_history records: {}; leasing_history records: {}; asset_history records: {}",
+            bh_uids.len(),
+            lh_uids.len(),
+            ah_uids.len()
+        );
+    }
 
-    Ok(())
+    Ok(bh_uids.len() + lh_uids.len() + ah_uids.len())
 }
 
-fn process(block: &BlockchainUpdateInfo, mut chunk: &mut Vec<BalanceHistory>) {
+fn process(block: &BlockchainUpdateInfo, chunk: &mut BalanceChunk) {
     let block_uid = block.uid.clone().unwrap();
     let block_height = block.height.clone().unwrap();
 
-    if let Some(block_state_update) = &block.state_updates {
+    // uid of rollback is the last block left, rows of removed blocks would be skipped on insert anyway
+    if block.block_type == BlockType::Rollback {
+        let (kept, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut chunk.balances)
+            .into_iter()
+            .partition(|b| b.block_uid <= block_uid);
+        chunk.balances = kept;
+
+        // saved rows are archived by blocks_microblocks::rollback, these never reached balance_history
+        if let Some(rollback_uid) = block.rollback_uid {
+            chunk
+                .rolled_back
+                .extend(removed.into_iter().map(|b| (rollback_uid, b)));
+        }
+
+        chunk.leasing.retain(|l| l.block_uid <= block_uid);
+        chunk.assets.retain(|a| a.block_uid <= block_uid);
+
+        if let Some(continuity) = chunk.continuity.as_mut() {
+            continuity.reset();
+        }
+
+        chunk.address_ids.reset_pending();
+        chunk.asset_ids.reset_pending();
+
+        return;
+    }
+
+    for (tx_id, state_update) in block.state_updates_with_tx_ids() {
         push_balances(
             block_height,
             &block_uid,
-            &block_state_update.balances,
-            &mut chunk,
+            tx_id.as_ref(),
+            &state_update.balances,
+            &chunk.filter,
+            &mut chunk.balances,
+        );
+
+        push_leasing(
+            block_height,
+            &block_uid,
+            tx_id.as_ref(),
+            &state_update.leasing_for_address,
+            &chunk.filter,
+            &mut chunk.leasing,
         );
-    };
 
-    for tr_s_upd in &block.transaction_state_updates {
-        push_balances(block_height, &block_uid, &tr_s_upd.balances, &mut chunk);
+        push_assets(
+            block_height,
+            &block_uid,
+            tx_id.as_ref(),
+            &state_update.assets,
+            &chunk.filter,
+            &mut chunk.assets,
+        );
     }
 }
 
 fn push_balances(
     block_height: u32,
     block_uid: &i64,
+    tx_id: Option<&String>,
     balances: &Vec<BalanceUpdate>,
+    filter: &IngestFilter,
     chunk: &mut Vec<BalanceHistory>,
 ) {
     for b in balances.iter() {
@@ -110,16 +271,144 @@ fn push_balances(
                     .replace(char::from(0), "");
                 let address = address.trim().replace(char::from(0), "");
 
+                if !filter.asset_allowed(&asset_id) || !filter.address_allowed(&address) {
+                    continue;
+                }
+
                 chunk.push(BalanceHistory {
                     block_uid: *block_uid,
                     address: address,
                     asset_id: asset_id,
                     amount: (*amount).into(),
+                    amount_before: b.amount_before.into(),
                     block_height: block_height,
+                    tx_id: tx_id.cloned(),
                 });
-                // println!("recipient: {}; asset_id: {}; block_uid: {}; amount: {}; b.amount_before: {}; ", address, &asset_id, block_uid, &amount, &b.amount_before);
             }
             None => {}
         }
     }
 }
+
+fn push_leasing(
+    block_height: u32,
+    block_uid: &i64,
+    tx_id: Option<&String>,
+    leasing: &Vec<LeasingUpdate>,
+    filter: &IngestFilter,
+    chunk: &mut Vec<LeasingHistory>,
+) {
+    for l in leasing.iter() {
+        let address = bs58::encode(&l.address)
+            .into_string()
+            .trim()
+            .replace(char::from(0), "");
+
+        if !filter.address_allowed(&address) {
+            continue;
+        }
+
+        chunk.push(LeasingHistory {
+            block_uid: *block_uid,
+            address: address,
+            lease_in: l.in_after.into(),
+            lease_out: l.out_after.into(),
+            block_height: block_height,
+            tx_id: tx_id.cloned(),
+        });
+    }
+}
+
+fn push_assets(
+    block_height: u32,
+    block_uid: &i64,
+    tx_id: Option<&String>,
+    assets: &Vec<AssetStateUpdate>,
+    filter: &IngestFilter,
+    chunk: &mut Vec<AssetHistory>,
+) {
+    for a in assets.iter() {
+        // state after the update, None means the asset is gone (rollback of issue)
+        if let Some(after) = &a.after {
+            let asset_id = bs58::encode(&after.asset_id)
+                .into_string()
+                .trim()
+                .replace(char::from(0), "");
+
+            if !filter.asset_allowed(&asset_id) {
+                continue;
+            }
+
+            let script = after
+                .script_info
+                .as_ref()
+                .map(|s| s.script.clone())
+                .filter(|s| !s.is_empty());
+
+            chunk.push(AssetHistory {
+                block_uid: *block_uid,
+                asset_id: asset_id,
+                quantity: after.volume.into(),
+                decimals: after.decimals,
+                name: after.name.clone(),
+                reissuable: after.reissuable,
+                script: script,
+                block_height: block_height,
+                tx_id: tx_id.cloned(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use waves_protobuf_schemas::waves::events::StateUpdate;
+
+    fn block(uid: i64, amount_before: i64, amount: i64) -> BlockchainUpdateInfo {
+        let balance = BalanceUpdate {
+            address: vec![1; 26],
+            amount_after: Some(Amount {
+                asset_id: vec![],
+                amount,
+            }),
+            amount_before,
+        };
+
+        BlockchainUpdateInfo {
+            uid: Some(uid),
+            height: Some(100),
+            block_type: BlockType::MicroBlock,
+            state_updates: Some(StateUpdate {
+                balances: vec![balance],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn replay_after_resume_keeps_only_replayed_rows() {
+        let mut chunk = BalanceChunk::default().with_continuity_check();
+        chunk.process(&block(10, 0, 5));
+        chunk.process(&block(11, 5, 7));
+
+        // resume deleted uid 11 and receives it again as uid 12
+        chunk.process(&BlockchainUpdateInfo {
+            uid: Some(10),
+            height: Some(99),
+            block_type: BlockType::Rollback,
+            ..Default::default()
+        });
+        chunk.process(&block(12, 5, 7));
+
+        let rows: Vec<(i64, Decimal)> = chunk
+            .balances
+            .iter()
+            .map(|b| (b.block_uid, b.amount_before))
+            .collect();
+        assert_eq!(rows, vec![(10, Decimal::from(0)), (12, Decimal::from(5))]);
+        // not a chain rollback, nothing to archive
+        assert!(chunk.rolled_back.is_empty());
+    }
+}
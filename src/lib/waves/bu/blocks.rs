@@ -1,39 +1,133 @@
+use super::chunk_analyzer::{SAVE_RETRY_INITIAL_MS, SAVE_RETRY_MAX_ATTEMPTS, SAVE_RETRY_MAX_SECS};
+use crate::consumer::backoff::Backoff;
 use crate::db::*;
 use crate::waves::BlockType;
 use crate::{consumer::SETTINGS, waves::BlockchainUpdateInfo};
-use wavesexchange_log::{error, info};
+use anyhow::anyhow;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use wavesexchange_log::{error, info, warn};
+
+// blocks younger than this are considered to be at the chain tip
+const TIP_LAG_MS: i64 = 60_000;
 
 pub struct Analyzer {
     db: Db,
     was_microblocks: bool,
     save_solidified: bool,
+    // microblocks were received since start or resume
+    at_tip: bool,
+    batch_size: usize,
+    // catch-up blocks with reserved uids, committed together
+    pending: Vec<(i64, String, i32, i64)>,
+    reserved_uids: VecDeque<i64>,
+    // max committed block uid, rows of other analyzers can't be saved before their blocks
+    committed_uid: i64,
+    committed_tx: watch::Sender<i64>,
+    committed_rx: watch::Receiver<i64>,
+    // blocks_rollbacks uid of the last rollback
+    rollback_uid: Option<i64>,
 }
 
 impl Analyzer {
-    pub async fn new() -> Self {
-        let db = Db::new(&SETTINGS.config.postgres).await.unwrap();
+    pub async fn new(batch_size: usize) -> Result<Self, anyhow::Error> {
+        let db = Db::new(&SETTINGS.config.postgres).await?;
+        let (committed_tx, committed_rx) = watch::channel(0);
 
-        Self {
+        Ok(Self {
             db: db,
             was_microblocks: false,
             save_solidified: true,
-        }
+            at_tip: false,
+            batch_size: batch_size,
+            pending: vec![],
+            reserved_uids: VecDeque::new(),
+            committed_uid: 0,
+            committed_tx: committed_tx,
+            committed_rx: committed_rx,
+            rollback_uid: None,
+        })
+    }
+
+    pub fn committed_uid(&self) -> watch::Receiver<i64> {
+        self.committed_rx.clone()
+    }
+
+    // chunk analyzers archive not yet saved rows of rolled back blocks under this rollback
+    pub fn last_rollback_uid(&self) -> Option<i64> {
+        self.rollback_uid
     }
 
     //blocks saves immediatly because uid need to other Analyzers
-    pub async fn send(&mut self, block: &BlockchainUpdateInfo) -> i64 {
-        let tr = self.db.transaction().await.unwrap();
+    //during catch-up blocks are saved in batches, uids are reserved in advance
+    //transient db errors are retried as in chunk analyzers, others stop the consumer
+    pub async fn send(&mut self, block: &BlockchainUpdateInfo) -> Result<i64, anyhow::Error> {
+        let mut backoff = Backoff::new(
+            Duration::from_millis(SAVE_RETRY_INITIAL_MS),
+            Duration::from_secs(SAVE_RETRY_MAX_SECS),
+        );
+
+        let mut attempt = 1;
+
+        loop {
+            match self.save(block).await {
+                Ok(uid) => return Ok(uid),
+                Err(err) if attempt < SAVE_RETRY_MAX_ATTEMPTS && is_transient_error(&err) => {
+                    let delay = backoff.next_delay();
+                    warn!(
+                        "save block attempt {} failed: {}; retry in {} ms",
+                        attempt,
+                        err,
+                        delay.as_millis()
+                    );
+
+                    tokio::time::sleep(delay).await;
+
+                    if self.db.is_closed() {
+                        match Db::new(&SETTINGS.config.postgres).await {
+                            Ok(new_db) => self.db = new_db,
+                            Err(e) => warn!("blocks analyzer reconnect failed: {}", e),
+                        }
+                    }
+
+                    attempt += 1;
+                }
+                Err(err) => {
+                    error!("save block failed: {}", err);
+                    return Err(err.context(format!(
+                        "can't save block {:?} after {} attempts",
+                        block.id, attempt
+                    )));
+                }
+            }
+        }
+    }
+
+    // state is changed only after commit, so a failed attempt can be repeated
+    async fn save(&mut self, block: &BlockchainUpdateInfo) -> Result<i64, anyhow::Error> {
+        if block.block_type == BlockType::MicroBlock {
+            self.at_tip = true;
+        }
+
+        if self.is_catching_up(block) {
+            return self.push_pending(block).await;
+        }
+
+        // uids must grow in order of blocks
+        self.flush().await?;
+        self.reserved_uids.clear();
+
+        let tr = self.db.transaction().await?;
+        let mut rollback_uid = None;
 
         let uid = match block.block_type {
             BlockType::MicroBlock => {
-                self.was_microblocks = true;
-
                 if self.save_solidified {
-                    mappers::blocks_microblocks::unsolidify_last_block(&tr).await;
-                    self.save_solidified = false;
+                    mappers::blocks_microblocks::unsolidify_last_block(&tr).await?;
                 }
 
-                let block_uid = mappers::blocks_microblocks::save(
+                mappers::blocks_microblocks::save(
                     &tr,
                     &block.id.clone().unwrap(),
                     &block.height.clone().unwrap(),
@@ -41,9 +135,7 @@ impl Analyzer {
                     false,
                     &block.block_type,
                 )
-                .await;
-
-                block_uid
+                .await?
             }
             BlockType::Block => {
                 if self.was_microblocks {
@@ -51,12 +143,10 @@ impl Analyzer {
                         &tr,
                         &block.reference_block_id.clone().unwrap(),
                     )
-                    .await;
-
-                    self.was_microblocks = false;
+                    .await?;
                 }
 
-                let block_uid = mappers::blocks_microblocks::save(
+                mappers::blocks_microblocks::save(
                     &tr,
                     &block.id.clone().unwrap(),
                     &block.height.clone().unwrap(),
@@ -64,15 +154,14 @@ impl Analyzer {
                     self.save_solidified,
                     &block.block_type,
                 )
-                .await;
-
-                block_uid
+                .await?
             }
             BlockType::Rollback => {
                 let block_id = block.id.clone().unwrap();
                 info!("rollback block: {}; ", block_id);
 
-                let max_uid = mappers::blocks_microblocks::rollback(&tr, &block_id).await;
+                let (max_uid, uid) = mappers::blocks_microblocks::rollback(&tr, &block_id).await?;
+                rollback_uid = Some(uid);
 
                 max_uid
             }
@@ -81,8 +170,157 @@ impl Analyzer {
             }
         };
 
-        tr.commit().await.unwrap();
+        tr.commit().await?;
+
+        match block.block_type {
+            BlockType::MicroBlock => {
+                self.was_microblocks = true;
+                self.save_solidified = false;
+            }
+            BlockType::Block => self.was_microblocks = false,
+            BlockType::Rollback => self.rollback_uid = rollback_uid,
+            BlockType::EMPTY => {}
+        }
+
+        self.set_committed(uid);
+
+        Ok(uid)
+    }
+
+    fn is_catching_up(&self, block: &BlockchainUpdateInfo) -> bool {
+        if self.batch_size <= 1 || self.at_tip || block.block_type != BlockType::Block {
+            return false;
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+
+        now - block.timestamp.unwrap_or(now) > TIP_LAG_MS
+    }
+
+    // a full batch is committed before the next block is added,
+    // so the block is not added twice when the commit is retried
+    async fn push_pending(&mut self, block: &BlockchainUpdateInfo) -> Result<i64, anyhow::Error> {
+        if self.pending.len() >= self.batch_size {
+            self.flush().await?;
+        }
+
+        if self.reserved_uids.is_empty() {
+            self.reserved_uids =
+                mappers::blocks_microblocks::reserve_uids(&self.db, self.batch_size as i32)
+                    .await?
+                    .into();
+        }
+
+        let uid = self
+            .reserved_uids
+            .pop_front()
+            .ok_or_else(|| anyhow!("no block uids reserved"))?;
+
+        self.pending.push((
+            uid,
+            block.id.clone().unwrap(),
+            block.height.unwrap() as i32,
+            block.timestamp.unwrap(),
+        ));
+
+        Ok(uid)
+    }
+
+    // commits pending catch-up blocks
+    pub async fn flush(&mut self) -> Result<(), anyhow::Error> {
+        let last_uid = match self.pending.last() {
+            Some(block) => block.0,
+            None => return Ok(()),
+        };
+
+        let tr = self.db.transaction().await?;
+
+        mappers::blocks_microblocks::save_solidified_bulk(&tr, &self.pending).await?;
+
+        tr.commit().await?;
+
+        self.pending.clear();
+        self.set_committed(last_uid);
+
+        Ok(())
+    }
+
+    fn set_committed(&mut self, uid: i64) {
+        if uid > self.committed_uid {
+            self.committed_uid = uid;
+            // no receivers is fine
+            let _ = self.committed_tx.send(uid);
+        }
+    }
+
+    // id of the solidified block at height, pending blocks are committed first
+    pub async fn block_id(&mut self, height: i32) -> Result<Option<String>, anyhow::Error> {
+        self.flush().await?;
+
+        let block = mappers::blocks_microblocks::get_solidified_block(&self.db, height).await?;
+
+        Ok(block.map(|(_, id)| id))
+    }
+
+    // drops not solidified blocks (balances are removed by cascade),
+    // returns height to resubscribe from and uid of the last block left if blocks were deleted
+    pub async fn resume(&mut self, start_height: i32) -> Result<(i32, Option<i64>), anyhow::Error> {
+        self.flush().await?;
+        self.reserved_uids.clear();
+
+        let tr = self.db.transaction().await?;
+
+        let deleted = mappers::blocks_microblocks::delete_unsolidified(&tr).await?;
+
+        tr.commit().await?;
+
+        info!("resume: deleted {} non solidified blocks", deleted.len());
+
+        let last_uid = if deleted.is_empty() {
+            None
+        } else {
+            Some(mappers::blocks_microblocks::get_max_uid(&self.db).await?)
+        };
+
+        self.was_microblocks = false;
+        self.save_solidified = true;
+        self.at_tip = false;
+
+        let height = match mappers::blocks_microblocks::get_last_height(&self.db).await {
+            None => start_height,
+            Some(last_h) => std::cmp::max(last_h + 1, start_height),
+        };
+
+        Ok((height, last_uid))
+    }
+
+    // for bounded ingestion: the last liquid block is final, merge its microblocks and mark everything solidified
+    pub async fn solidify_all(&mut self) -> Result<(), anyhow::Error> {
+        self.flush().await?;
+
+        let tr = self.db.transaction().await?;
+
+        if self.was_microblocks {
+            if let Some(last_id) = mappers::blocks_microblocks::get_last_id(&tr).await? {
+                mappers::blocks_microblocks::solidify(&tr, &last_id).await?;
+            }
+            self.was_microblocks = false;
+        }
+
+        let solidified = mappers::blocks_microblocks::solidify_all(&tr).await?;
+
+        tr.commit().await?;
+
+        // nothing is left to roll back, so the next start keeps all blocks
+        if let Some(height) = mappers::blocks_microblocks::get_last_height(&self.db).await {
+            mappers::safe_heights::set_all(&self.db, height).await?;
+        }
+
+        info!("solidify all: {} blocks solidified", solidified);
 
-        uid
+        Ok(())
     }
 }
@@ -0,0 +1,429 @@
+use crate::config::consumer::FlushConfig;
+use crate::consumer::{backoff::Backoff, health::HEALTH, metrics, SETTINGS};
+use crate::db::{mappers::safe_heights, *};
+use crate::waves::BlockchainUpdateInfo;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::{
+    select,
+    sync::{
+        mpsc::{self, Sender},
+        watch,
+    },
+    task::JoinHandle,
+    time::Instant,
+};
+use tokio_postgres::Transaction;
+use wavesexchange_log::{error, warn};
+
+pub const SAVE_RETRY_MAX_ATTEMPTS: u32 = 10;
+pub const SAVE_RETRY_INITIAL_MS: u64 = 500;
+pub const SAVE_RETRY_MAX_SECS: u64 = 30;
+
+// rows collected from blocks and saved by ChunkAnalyzer in one transaction
+#[async_trait]
+pub trait Chunk: Send + Sync + 'static {
+    // used in logs
+    fn name(&self) -> &'static str;
+
+    // table_name in safe_heights
+    fn safe_heights_table(&self) -> &'static str;
+
+    // tables with rows of the chunk, all linked by block_uid to blocks_microblocks
+    fn history_tables(&self) -> &'static [&'static str];
+
+    fn process(&mut self, block: &BlockchainUpdateInfo);
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn clear(&mut self);
+
+    fn min_height(&self) -> Option<u32>;
+
+    // inserts rows without touching safe_heights, returns count of saved rows
+    async fn save_rows(&self, tr: &Transaction<'_>) -> Result<usize, anyhow::Error>;
+
+    // called after the transaction of save is committed
+    fn saved(&mut self) {}
+
+    // the last height may still get microblocks
+    fn safe_height(&self, height: u32) -> u32 {
+        let min_height = self.min_height().map_or(height, |h| h.min(height));
+        min_height.saturating_sub(1)
+    }
+
+    // rows must stay in the chunk on error, save is retried with the same chunk;
+    // height is the last processed block, safe height moves even when the chunk has no rows
+    async fn save(&mut self, db: &mut Db, height: u32) -> Result<(), anyhow::Error> {
+        let tr = db.transaction().await?;
+
+        self.save_rows(&tr).await?;
+
+        safe_heights::save(&tr, self.safe_heights_table(), self.safe_height(height)).await?;
+
+        tr.commit().await?;
+
+        self.saved();
+
+        Ok(())
+    }
+}
+
+// receives blocks (with uid already set by blocks::Analyzer) and saves collected chunks in a separate task
+pub struct ChunkAnalyzer {
+    name: &'static str,
+    sender: Sender<BlockchainUpdateInfo>,
+    task: Option<JoinHandle<Result<(), anyhow::Error>>>,
+}
+
+impl ChunkAnalyzer {
+    // committed_uid comes from blocks::Analyzer, blocks of a chunk must be committed before it is saved
+    pub async fn new(
+        chunk: Box<dyn Chunk>,
+        buf_size: usize,
+        committed_uid: watch::Receiver<i64>,
+    ) -> Self {
+        let name = chunk.name();
+        let (tx, rx) = mpsc::channel::<BlockchainUpdateInfo>(buf_size);
+
+        HEALTH.set_analyzer_alive(name, true);
+        metrics::ANALYZER_BACKLOG.with_label_values(&[name]).set(0);
+
+        let task = tokio::spawn(async move {
+            let res = run_task(chunk, rx, committed_uid).await;
+            HEALTH.set_analyzer_alive(name, false);
+            res
+        });
+
+        Self {
+            name,
+            sender: tx,
+            task: Some(task),
+        }
+    }
+
+    // fails if the saving task is stopped, the error of the task is returned
+    pub async fn send(&mut self, block: &BlockchainUpdateInfo) -> Result<(), anyhow::Error> {
+        let backlog = metrics::ANALYZER_BACKLOG.with_label_values(&[self.name]);
+
+        // increased before send, the task may receive the block at once
+        backlog.inc();
+
+        if self.sender.send(block.clone()).await.is_err() {
+            backlog.dec();
+            let name = self.name;
+            return Err(task_result(name, &mut self.task)
+                .await
+                .err()
+                .unwrap_or_else(|| {
+                    anyhow!(
+                        "{} analyzer task exited before the channel was closed",
+                        name
+                    )
+                }));
+        }
+
+        Ok(())
+    }
+
+    // closes the channel and waits until the last chunk is saved
+    pub async fn finish(self) -> Result<(), anyhow::Error> {
+        let Self {
+            name,
+            sender,
+            mut task,
+            ..
+        } = self;
+        drop(sender);
+
+        task_result(name, &mut task).await
+    }
+}
+
+// processes blocks until the channel is closed,
+// chunk is saved when any limit of the flush policy is reached
+async fn run_task(
+    mut chunk: Box<dyn Chunk>,
+    mut rx: mpsc::Receiver<BlockchainUpdateInfo>,
+    mut committed_uid: watch::Receiver<i64>,
+) -> Result<(), anyhow::Error> {
+    let policy = &SETTINGS.config.flush;
+    let max_latency = Duration::from_millis(policy.max_latency_ms);
+
+    let mut db = Db::new(&SETTINGS.config.postgres).await?;
+
+    let mut last_uid = 0;
+    // height of the last processed block and the one safe_heights was saved for
+    let mut height = Heights::default();
+    let mut blocks = 0;
+    // when the first not saved block was received
+    let mut first_unsaved: Option<Instant> = None;
+
+    loop {
+        let deadline = first_unsaved.map(|t| t + max_latency);
+
+        let block = select! {
+            block = rx.recv() => block,
+            _ = wait_deadline(deadline) => {
+                // blocks of an uncommitted batch: rows and blocks limits are reached first
+                if *committed_uid.borrow() >= last_uid {
+                    flush(
+                        &mut db,
+                        chunk.as_mut(),
+                        &mut committed_uid,
+                        last_uid,
+                        &mut height,
+                        "latency",
+                    )
+                    .await?;
+                    blocks = 0;
+                    first_unsaved = None;
+                } else {
+                    first_unsaved = Some(Instant::now());
+                }
+                continue;
+            }
+        };
+
+        let block = match block {
+            Some(block) => block,
+            None => break,
+        };
+
+        metrics::ANALYZER_BACKLOG
+            .with_label_values(&[chunk.name()])
+            .dec();
+
+        chunk.process(&block);
+        last_uid = block.uid.unwrap_or(last_uid);
+        height.processed = block.height.or(height.processed);
+        blocks += 1;
+
+        let received = *first_unsaved.get_or_insert_with(Instant::now);
+
+        if let Some(reason) = flush_reason(policy, chunk.len(), blocks, received.elapsed()) {
+            flush(
+                &mut db,
+                chunk.as_mut(),
+                &mut committed_uid,
+                last_uid,
+                &mut height,
+                reason,
+            )
+            .await?;
+            blocks = 0;
+            first_unsaved = None;
+        }
+    }
+
+    // channel closed: save what is left
+    flush(
+        &mut db,
+        chunk.as_mut(),
+        &mut committed_uid,
+        last_uid,
+        &mut height,
+        "close",
+    )
+    .await
+}
+
+// limit of the flush policy reached by the chunk, used as metrics label
+fn flush_reason(
+    policy: &FlushConfig,
+    rows: usize,
+    blocks: usize,
+    unsaved_for: Duration,
+) -> Option<&'static str> {
+    if rows >= policy.max_rows {
+        Some("rows")
+    } else if policy.max_blocks > 0 && blocks >= policy.max_blocks {
+        Some("blocks")
+    } else if unsaved_for >= Duration::from_millis(policy.max_latency_ms) {
+        Some("latency")
+    } else {
+        None
+    }
+}
+
+async fn wait_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[derive(Default)]
+struct Heights {
+    processed: Option<u32>,
+    saved: Option<u32>,
+}
+
+async fn flush(
+    db: &mut Db,
+    chunk: &mut dyn Chunk,
+    committed_uid: &mut watch::Receiver<i64>,
+    last_uid: i64,
+    height: &mut Heights,
+    reason: &str,
+) -> Result<(), anyhow::Error> {
+    let processed = match height.processed {
+        Some(h) => h,
+        None => return Ok(()),
+    };
+
+    // filtered out blocks still move the safe height
+    if chunk.is_empty() && height.saved == Some(processed) {
+        return Ok(());
+    }
+
+    metrics::CHUNK_FLUSHES
+        .with_label_values(&[chunk.name(), reason])
+        .inc();
+
+    wait_committed(committed_uid, last_uid).await?;
+    // chunk is kept until it is saved, so nothing is lost between retries
+    save_chunk_with_retry(db, chunk, processed).await?;
+    chunk.clear();
+    height.saved = Some(processed);
+
+    Ok(())
+}
+
+async fn task_result(
+    name: &str,
+    task: &mut Option<JoinHandle<Result<(), anyhow::Error>>>,
+) -> Result<(), anyhow::Error> {
+    match task.take() {
+        Some(task) => match task.await {
+            Ok(res) => res,
+            Err(err) => Err(anyhow!("{} analyzer task panic: {}", name, err)),
+        },
+        None => Err(anyhow!("{} analyzer task already stopped", name)),
+    }
+}
+
+// rows of not yet committed blocks would be skipped by the join with blocks_microblocks
+async fn wait_committed(
+    committed_uid: &mut watch::Receiver<i64>,
+    uid: i64,
+) -> Result<(), anyhow::Error> {
+    while *committed_uid.borrow() < uid {
+        committed_uid
+            .changed()
+            .await
+            .map_err(|_| anyhow!("blocks analyzer stopped before block {} was committed", uid))?;
+    }
+
+    Ok(())
+}
+
+async fn save_chunk_with_retry(
+    db: &mut Db,
+    chunk: &mut dyn Chunk,
+    height: u32,
+) -> Result<(), anyhow::Error> {
+    let mut backoff = Backoff::new(
+        Duration::from_millis(SAVE_RETRY_INITIAL_MS),
+        Duration::from_secs(SAVE_RETRY_MAX_SECS),
+    );
+
+    let mut attempt = 1;
+    let rows = chunk.len();
+    let timer = metrics::CHUNK_FLUSH_DURATION
+        .with_label_values(&[chunk.name()])
+        .start_timer();
+
+    loop {
+        match chunk.save(db, height).await {
+            Ok(()) => {
+                timer.observe_duration();
+                metrics::CHUNK_FLUSH_ROWS
+                    .with_label_values(&[chunk.name()])
+                    .observe(rows as f64);
+                return Ok(());
+            }
+            Err(err) if attempt < SAVE_RETRY_MAX_ATTEMPTS && is_transient_error(&err) => {
+                let delay = backoff.next_delay();
+                warn!(
+                    "save {} chunk attempt {} failed: {}; retry in {} ms",
+                    chunk.name(),
+                    attempt,
+                    err,
+                    delay.as_millis()
+                );
+
+                tokio::time::sleep(delay).await;
+
+                if db.is_closed() {
+                    match Db::new(&SETTINGS.config.postgres).await {
+                        Ok(new_db) => *db = new_db,
+                        Err(e) => warn!("{} analyzer reconnect failed: {}", chunk.name(), e),
+                    }
+                }
+
+                attempt += 1;
+            }
+            Err(err) => {
+                error!("save {} chunk failed: {}", chunk.name(), err);
+                return Err(err.context(format!(
+                    "can't save {} chunk after {} attempts",
+                    chunk.name(),
+                    attempt
+                )));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: FlushConfig = FlushConfig {
+        max_rows: 1000,
+        max_blocks: 10,
+        max_latency_ms: 1000,
+    };
+
+    #[test]
+    fn empty_chunk_reaches_time_limit() {
+        let before = flush_reason(&POLICY, 0, 1, Duration::from_millis(999));
+        let reached = flush_reason(&POLICY, 0, 1, Duration::from_millis(1000));
+
+        assert_eq!(before, None);
+        assert_eq!(reached, Some("latency"));
+    }
+
+    #[test]
+    fn rows_limit_is_reported_before_others() {
+        let reason = flush_reason(&POLICY, 1000, 10, Duration::from_secs(1));
+
+        assert_eq!(reason, Some("rows"));
+    }
+
+    #[test]
+    fn zero_blocks_limit_is_unlimited() {
+        let policy = FlushConfig {
+            max_blocks: 0,
+            ..POLICY
+        };
+
+        assert_eq!(flush_reason(&policy, 0, 1_000_000, Duration::ZERO), None);
+    }
+
+    #[test]
+    fn zero_time_limit_flushes_every_block() {
+        let policy = FlushConfig {
+            max_latency_ms: 0,
+            ..POLICY
+        };
+
+        assert_eq!(flush_reason(&policy, 0, 1, Duration::ZERO), Some("latency"));
+    }
+}
@@ -0,0 +1,137 @@
+use super::balance_updates::BalanceHistory;
+use crate::consumer::metrics::BALANCE_DISCREPANCIES;
+use crate::db::mappers::{balance_discrepancies, balance_history};
+use itertools::Itertools;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio_postgres::Transaction;
+use wavesexchange_log::warn;
+
+// cache is dropped as a whole when it grows over this size
+const CACHE_MAX_SIZE: usize = 1_000_000;
+
+#[derive(Debug)]
+pub struct Discrepancy {
+    pub block_uid: i64,
+    pub address_id: i64,
+    pub asset_id: i64,
+    pub expected: Decimal,
+    pub amount_before: Decimal,
+    pub tx_id: Option<String>,
+}
+
+// amount_before of every balance update must equal the last stored amount of (address, asset),
+// otherwise some updates were lost (missed rollback, crash between saves, bug in push_balances)
+#[derive(Debug, Default)]
+pub struct ContinuityCheck {
+    // last amount by (address_id, asset_id), only committed amounts get here
+    cache: HashMap<(i64, i64), Decimal>,
+    // last amounts and count of discrepancies of the chunk being saved
+    pending: Mutex<Option<(HashMap<(i64, i64), Decimal>, usize)>>,
+}
+
+impl ContinuityCheck {
+    // saves discrepancies found in balances, pairs missing in the cache are read from balance_history,
+    // so it must run before balances are inserted
+    pub async fn check(
+        &self,
+        tr: &Transaction<'_>,
+        balances: &[BalanceHistory],
+        assets_map: &HashMap<String, i64>,
+        address_map: &HashMap<String, i64>,
+    ) -> Result<usize, anyhow::Error> {
+        let rows: Vec<((i64, i64), &BalanceHistory)> = balances
+            .iter()
+            .map(|b| ((address_map[&b.address], assets_map[&b.asset_id]), b))
+            .collect();
+
+        let missing = self.missing(rows.iter().map(|(key, _)| *key));
+
+        let stored = balance_history::last_amounts(tr, &missing).await?;
+
+        let mut last: HashMap<(i64, i64), Decimal> = HashMap::new();
+        let mut discrepancies = vec![];
+
+        for (key, b) in rows {
+            let expected = last
+                .get(&key)
+                .or_else(|| self.cache.get(&key))
+                .or_else(|| stored.get(&key))
+                .copied();
+
+            // nothing to compare with for the first update of the pair
+            if let Some(expected) = expected {
+                if expected != b.amount_before {
+                    warn!(
+                        "amount_before discrepancy: address: {}; asset_id: {}; block_uid: {}; expected: {}; amount_before: {}",
+                        b.address, b.asset_id, b.block_uid, expected, b.amount_before
+                    );
+
+                    discrepancies.push(Discrepancy {
+                        block_uid: b.block_uid,
+                        address_id: key.0,
+                        asset_id: key.1,
+                        expected: expected,
+                        amount_before: b.amount_before,
+                        tx_id: b.tx_id.clone(),
+                    });
+                }
+            }
+
+            last.insert(key, b.amount);
+        }
+
+        balance_discrepancies::save_bulk(tr, &discrepancies).await?;
+
+        let found = discrepancies.len();
+        *self.pending.lock().unwrap() = Some((last, found));
+
+        Ok(found)
+    }
+
+    // pairs to read from balance_history
+    fn missing(&self, keys: impl Iterator<Item = (i64, i64)>) -> Vec<(i64, i64)> {
+        keys.filter(|key| !self.cache.contains_key(key))
+            .unique()
+            .collect()
+    }
+
+    // called after the chunk is committed
+    pub fn commit(&mut self) {
+        if let Some((last, found)) = self.pending.get_mut().unwrap().take() {
+            if self.cache.len() + last.len() > CACHE_MAX_SIZE {
+                self.cache.clear();
+            }
+
+            self.cache.extend(last);
+            BALANCE_DISCREPANCIES.inc_by(found as u64);
+        }
+    }
+
+    // stored amounts of rolled back blocks are gone
+    pub fn reset(&mut self) {
+        self.cache.clear();
+        self.pending.get_mut().unwrap().take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts_are_read_again_after_resume() {
+        let mut continuity = ContinuityCheck::default();
+        let last = HashMap::from([((1, 1), Decimal::from(7))]);
+        *continuity.pending.get_mut().unwrap() = Some((last, 0));
+        continuity.commit();
+
+        let keys = [(1, 1), (2, 1), (2, 1)];
+        assert_eq!(continuity.missing(keys.into_iter()), vec![(2, 1)]);
+
+        // amount 7 was stored by a deleted block, replayed blocks are compared with balance_history
+        continuity.reset();
+        assert_eq!(continuity.missing(keys.into_iter()), vec![(1, 1), (2, 1)]);
+    }
+}
@@ -0,0 +1,177 @@
+use super::chunk_analyzer::Chunk;
+use super::filters::IngestFilter;
+use super::id_cache::{self, IdCache};
+use crate::db::mappers::data_entries_history;
+use crate::waves::BlockchainUpdateInfo;
+use async_trait::async_trait;
+use tokio_postgres::Transaction;
+use waves_protobuf_schemas::waves::{
+    data_transaction_data::data_entry::Value, events::state_update::DataEntryUpdate,
+};
+use wavesexchange_log::info;
+
+const DEH_TABLE_NAME: &str = "data_entries_history";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataValue {
+    Int(i64),
+    Bool(bool),
+    Binary(Vec<u8>),
+    String(String),
+}
+
+#[derive(Debug)]
+pub struct DataEntryHistory {
+    pub block_uid: i64,
+    pub address: String,
+    pub key: String,
+    // None when the entry is deleted
+    pub value: Option<DataValue>,
+    pub block_height: u32,
+    // None for block level changes
+    pub tx_id: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct DataEntriesChunk {
+    pub entries: Vec<DataEntryHistory>,
+    filter: IngestFilter,
+    address_ids: IdCache,
+}
+
+impl DataEntriesChunk {
+    pub fn new(filter: IngestFilter) -> Self {
+        Self {
+            filter: filter,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_id_cache_size(mut self, size: usize) -> Self {
+        self.address_ids = IdCache::new(size);
+        self
+    }
+}
+
+#[async_trait]
+impl Chunk for DataEntriesChunk {
+    fn name(&self) -> &'static str {
+        "data_entries"
+    }
+
+    fn safe_heights_table(&self) -> &'static str {
+        DEH_TABLE_NAME
+    }
+
+    fn history_tables(&self) -> &'static [&'static str] {
+        &[DEH_TABLE_NAME]
+    }
+
+    fn process(&mut self, block: &BlockchainUpdateInfo) {
+        let block_uid = block.uid.clone().unwrap();
+        let block_height = block.height.clone().unwrap();
+
+        for (tx_id, state_update) in block.state_updates_with_tx_ids() {
+            push_data_entries(
+                block_height,
+                &block_uid,
+                tx_id.as_ref(),
+                &state_update.data_entries,
+                &self.filter,
+                &mut self.entries,
+            );
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn min_height(&self) -> Option<u32> {
+        self.entries.iter().map(|e| e.block_height).min()
+    }
+
+    async fn save_rows(&self, tr: &Transaction<'_>) -> Result<usize, anyhow::Error> {
+        let addresses: Vec<&str> = self.entries.iter().map(|e| e.address.as_str()).collect();
+        let address_map = id_cache::merge_addresses(tr, &self.address_ids, &addresses).await?;
+
+        let uids = data_entries_history::save_bulk(tr, &self.entries, &address_map).await?;
+
+        if !uids.is_empty() {
+            info!("bulk saved data_entries_history records: {}", uids.len());
+        }
+
+        Ok(uids.len())
+    }
+
+    fn saved(&mut self) {
+        self.address_ids.commit();
+    }
+}
+
+fn push_data_entries(
+    block_height: u32,
+    block_uid: &i64,
+    tx_id: Option<&String>,
+    data_entries: &Vec<DataEntryUpdate>,
+    filter: &IngestFilter,
+    chunk: &mut Vec<DataEntryHistory>,
+) {
+    for d in data_entries.iter() {
+        if let Some(entry) = &d.data_entry {
+            let address = bs58::encode(&d.address)
+                .into_string()
+                .trim()
+                .replace(char::from(0), "");
+
+            if !filter.address_allowed(&address) {
+                continue;
+            }
+
+            let value = entry.value.as_ref().map(|v| match v {
+                Value::IntValue(i) => DataValue::Int(*i),
+                Value::BoolValue(b) => DataValue::Bool(*b),
+                Value::BinaryValue(b) => DataValue::Binary(b.clone()),
+                Value::StringValue(s) => DataValue::String(s.clone()),
+            });
+
+            chunk.push(DataEntryHistory {
+                block_uid: *block_uid,
+                address: address,
+                key: entry.key.clone(),
+                value: value,
+                block_height: block_height,
+                tx_id: tx_id.cloned(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_chunk_moves_safe_height_with_processed_blocks() {
+        let mut chunk = DataEntriesChunk::default();
+        assert_eq!(chunk.safe_height(100), 99);
+
+        chunk.entries.push(DataEntryHistory {
+            block_uid: 1,
+            address: "address".to_owned(),
+            key: "key".to_owned(),
+            value: Some(DataValue::Int(1)),
+            block_height: 90,
+            tx_id: None,
+        });
+        assert_eq!(chunk.safe_height(100), 89);
+
+        // saved rows don't hold it back
+        chunk.clear();
+        assert_eq!(chunk.safe_height(101), 100);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::config::consumer::IngestFilterConfig;
+use crate::db::{mappers::ingest_filters, Db};
+use anyhow::anyhow;
+use std::collections::HashSet;
+use wavesexchange_log::info;
+
+// asset id of WAVES in balance updates
+const WAVES_ASSET_ID: &str = "";
+
+// decides which balance, leasing, asset and data entry changes are stored,
+// blocks_microblocks is always complete
+#[derive(Debug, Clone, Default)]
+pub struct IngestFilter {
+    include_assets: HashSet<String>,
+    exclude_assets: HashSet<String>,
+    include_addresses: HashSet<String>,
+    exclude_addresses: HashSet<String>,
+}
+
+impl IngestFilter {
+    pub fn new(config: &IngestFilterConfig) -> Self {
+        let mut filter = Self::default();
+
+        config
+            .include_assets
+            .iter()
+            .for_each(|a| filter.add("asset", "include", a));
+        config
+            .exclude_assets
+            .iter()
+            .for_each(|a| filter.add("asset", "exclude", a));
+        config
+            .include_addresses
+            .iter()
+            .for_each(|a| filter.add("address", "include", a));
+        config
+            .exclude_addresses
+            .iter()
+            .for_each(|a| filter.add("address", "exclude", a));
+
+        filter
+    }
+
+    // config lists merged with ingest_filters table, read once on start
+    pub async fn load(db: &Db, config: &IngestFilterConfig) -> Result<Self, anyhow::Error> {
+        let mut filter = Self::new(config);
+
+        for (kind, mode, value) in ingest_filters::get_all(db).await? {
+            if !filter.add(&kind, &mode, &value) {
+                return Err(anyhow!(
+                    "invalid ingest filter kind: {} mode: {}",
+                    kind,
+                    mode
+                ));
+            }
+        }
+
+        if !filter.is_empty() {
+            info!(
+                "ingest filter: include assets: {}; exclude assets: {}; include addresses: {}; exclude addresses: {}",
+                filter.include_assets.len(),
+                filter.exclude_assets.len(),
+                filter.include_addresses.len(),
+                filter.exclude_addresses.len()
+            );
+        }
+
+        Ok(filter)
+    }
+
+    fn add(&mut self, kind: &str, mode: &str, value: &str) -> bool {
+        let value = value.trim();
+
+        // empty items of env lists
+        if value.is_empty() {
+            return true;
+        }
+
+        let value = match (kind, value) {
+            ("asset", "WAVES") => WAVES_ASSET_ID.to_owned(),
+            _ => value.to_owned(),
+        };
+
+        let set = match (kind, mode) {
+            ("asset", "include") => &mut self.include_assets,
+            ("asset", "exclude") => &mut self.exclude_assets,
+            ("address", "include") => &mut self.include_addresses,
+            ("address", "exclude") => &mut self.exclude_addresses,
+            _ => return false,
+        };
+
+        set.insert(value);
+
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include_assets.is_empty()
+            && self.exclude_assets.is_empty()
+            && self.include_addresses.is_empty()
+            && self.exclude_addresses.is_empty()
+    }
+
+    // exclude wins over include
+    pub fn asset_allowed(&self, asset_id: &str) -> bool {
+        (self.include_assets.is_empty() || self.include_assets.contains(asset_id))
+            && !self.exclude_assets.contains(asset_id)
+    }
+
+    pub fn address_allowed(&self, address: &str) -> bool {
+        (self.include_addresses.is_empty() || self.include_addresses.contains(address))
+            && !self.exclude_addresses.contains(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_items_of_env_lists_are_skipped() {
+        // EXCLUDE_ADDRESSES="a, ,b," splits into empty and padded items
+        let config = IngestFilterConfig {
+            include_assets: vec![],
+            exclude_assets: vec!["".to_owned()],
+            include_addresses: vec![],
+            exclude_addresses: vec![" a".to_owned(), " ".to_owned(), "b ".to_owned()],
+        };
+
+        let filter = IngestFilter::new(&config);
+
+        assert!(filter.asset_allowed(WAVES_ASSET_ID));
+        assert!(!filter.address_allowed("a"));
+        assert!(!filter.address_allowed("b"));
+        assert!(filter.address_allowed(""));
+    }
+
+    #[test]
+    fn waves_alias_is_for_assets_only() {
+        let mut filter = IngestFilter::default();
+        filter.add("asset", "include", "WAVES");
+        filter.add("address", "exclude", "WAVES");
+
+        assert!(filter.asset_allowed(WAVES_ASSET_ID));
+        assert!(!filter.asset_allowed("WAVES"));
+        assert!(!filter.address_allowed("WAVES"));
+        assert!(filter.address_allowed(WAVES_ASSET_ID));
+    }
+
+    #[test]
+    fn excluded_asset_of_include_list_is_skipped() {
+        let mut filter = IngestFilter::default();
+        filter.add("asset", "include", "a");
+        filter.add("asset", "include", "b");
+        filter.add("asset", "exclude", "b");
+
+        assert!(filter.asset_allowed("a"));
+        assert!(!filter.asset_allowed("b"));
+        assert!(!filter.asset_allowed(WAVES_ASSET_ID));
+    }
+
+    #[test]
+    fn unknown_kind_or_mode_of_table_row_is_rejected() {
+        let mut filter = IngestFilter::default();
+
+        assert!(!filter.add("token", "include", "a"));
+        assert!(!filter.add("asset", "only", "a"));
+        assert!(filter.is_empty());
+    }
+}
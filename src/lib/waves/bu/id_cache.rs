@@ -0,0 +1,176 @@
+use crate::db::mappers::{unique_address, unique_assets};
+use lru::LruCache;
+use std::collections::HashMap;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use tokio_postgres::Transaction;
+
+pub const DEFAULT_ID_CACHE_SIZE: usize = 100_000;
+
+// uids of unique_address/unique_assets values known to be committed;
+// rows of these tables are never deleted, so cached uids stay valid after blockchain rollbacks
+pub struct IdCache {
+    cache: Mutex<LruCache<String, i64>>,
+    // uids merged in the transaction being saved, may be rolled back together with it
+    pending: Mutex<HashMap<String, i64>>,
+}
+
+impl IdCache {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::new(1).unwrap());
+
+        Self {
+            cache: Mutex::new(LruCache::new(capacity)),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // returns cached uids and values to merge
+    fn lookup<'a>(&self, values: &[&'a str]) -> (HashMap<String, i64>, Vec<&'a str>) {
+        let mut cache = self.cache.lock().unwrap();
+        let mut found = HashMap::new();
+        let mut missing = vec![];
+
+        for v in values {
+            match cache.get(*v) {
+                Some(uid) => {
+                    found.insert(v.to_string(), *uid);
+                }
+                None => missing.push(*v),
+            }
+        }
+
+        (found, missing)
+    }
+
+    // replaces uids left by a failed save
+    fn stage(&self, merged: &HashMap<String, i64>) {
+        *self.pending.lock().unwrap() = merged.clone();
+    }
+
+    // uids merged by a save that is not going to be committed
+    pub fn reset_pending(&mut self) {
+        self.pending.get_mut().unwrap().clear();
+    }
+
+    // called after the transaction is committed
+    pub fn commit(&mut self) {
+        let pending = std::mem::take(self.pending.get_mut().unwrap());
+        let cache = self.cache.get_mut().unwrap();
+
+        for (value, uid) in pending {
+            cache.put(value, uid);
+        }
+    }
+}
+
+impl Default for IdCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_ID_CACHE_SIZE)
+    }
+}
+
+impl fmt::Debug for IdCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cache = self.cache.lock().unwrap();
+        write!(f, "IdCache({}/{})", cache.len(), cache.cap())
+    }
+}
+
+pub async fn merge_addresses(
+    tr: &Transaction<'_>,
+    cache: &IdCache,
+    addresses: &[&str],
+) -> Result<HashMap<String, i64>, anyhow::Error> {
+    let (mut uids, missing) = cache.lookup(addresses);
+
+    let merged = unique_address::merge_bulk(tr, &missing).await?;
+    cache.stage(&merged);
+    uids.extend(merged);
+
+    Ok(uids)
+}
+
+pub async fn merge_assets(
+    tr: &Transaction<'_>,
+    cache: &IdCache,
+    assets: &[&str],
+) -> Result<HashMap<String, i64>, anyhow::Error> {
+    let (mut uids, missing) = cache.lookup(assets);
+
+    let merged = unique_assets::merge_bulk(tr, &missing).await?;
+    cache.stage(&merged);
+    uids.extend(merged);
+
+    Ok(uids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uids(values: &[(&str, i64)]) -> HashMap<String, i64> {
+        values
+            .iter()
+            .map(|(v, uid)| (v.to_string(), *uid))
+            .collect()
+    }
+
+    #[test]
+    fn staged_uids_are_cached_after_commit() {
+        let mut cache = IdCache::new(10);
+
+        cache.stage(&uids(&[("a", 1), ("b", 2)]));
+
+        // not committed yet
+        let (found, missing) = cache.lookup(&["a", "b"]);
+        assert!(found.is_empty());
+        assert_eq!(missing, vec!["a", "b"]);
+
+        cache.commit();
+
+        let (found, missing) = cache.lookup(&["a", "b", "c"]);
+        assert_eq!(found, uids(&[("a", 1), ("b", 2)]));
+        assert_eq!(missing, vec!["c"]);
+    }
+
+    #[test]
+    fn uids_of_failed_save_are_dropped() {
+        let mut cache = IdCache::new(10);
+
+        // the first save is rolled back, the retry merges again
+        cache.stage(&uids(&[("a", 1)]));
+        cache.stage(&uids(&[("b", 2)]));
+        cache.commit();
+
+        let (found, missing) = cache.lookup(&["a", "b"]);
+        assert_eq!(found, uids(&[("b", 2)]));
+        assert_eq!(missing, vec!["a"]);
+    }
+
+    #[test]
+    fn least_recently_used_uids_are_evicted() {
+        let mut cache = IdCache::new(2);
+
+        cache.stage(&uids(&[("a", 1), ("b", 2)]));
+        cache.commit();
+        cache.lookup(&["a"]);
+        cache.stage(&uids(&[("c", 3)]));
+        cache.commit();
+
+        let (found, missing) = cache.lookup(&["a", "b", "c"]);
+        assert_eq!(found, uids(&[("a", 1), ("c", 3)]));
+        assert_eq!(missing, vec!["b"]);
+    }
+
+    #[test]
+    fn zero_capacity_keeps_one_uid() {
+        let mut cache = IdCache::new(0);
+
+        cache.stage(&uids(&[("a", 1)]));
+        cache.commit();
+
+        assert_eq!(cache.lookup(&["a"]).0, uids(&[("a", 1)]));
+    }
+}
@@ -1,2 +1,7 @@
 pub mod balance_updates;
 pub mod blocks;
+pub mod chunk_analyzer;
+pub mod continuity;
+pub mod data_entries;
+pub mod filters;
+pub mod id_cache;
@@ -53,6 +53,8 @@ pub struct BlockchainUpdateInfo {
     pub transaction_state_updates: Vec<StateUpdate>,
     pub block_type: BlockType,
     pub rollback_data: Option<Rollback>,
+    // blocks_rollbacks row saved for a rollback by blocks::Analyzer
+    pub rollback_uid: Option<i64>,
 }
 
 impl Default for BlockchainUpdateInfo {
@@ -70,10 +72,34 @@ impl Default for BlockchainUpdateInfo {
             transactions_metadata: vec![],
             transaction_state_updates: vec![],
             rollback_data: None,
+            rollback_uid: None,
         }
     }
 }
 
+impl BlockchainUpdateInfo {
+    // block level state update (without tx id) followed by state updates of transactions with base58 tx ids
+    pub fn state_updates_with_tx_ids(&self) -> Vec<(Option<String>, &StateUpdate)> {
+        let mut updates = Vec::with_capacity(self.transaction_state_updates.len() + 1);
+
+        if let Some(block_state_update) = &self.state_updates {
+            updates.push((None, block_state_update));
+        }
+
+        // transaction_ids and transaction_state_updates are in the same order
+        for (idx, tr_s_upd) in self.transaction_state_updates.iter().enumerate() {
+            let tx_id = self
+                .transaction_ids
+                .get(idx)
+                .map(|id| bs58::encode(id).into_string());
+
+            updates.push((tx_id, tr_s_upd));
+        }
+
+        updates
+    }
+}
+
 impl From<Option<SubscribeEvent>> for BlockchainUpdateInfo {
     fn from(event: Option<SubscribeEvent>) -> Self {
         let mut block_data = BlockchainUpdateInfo::default();
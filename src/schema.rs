@@ -9,6 +9,33 @@ table! {
     }
 }
 
+table! {
+    asset_history (uid) {
+        uid -> Int8,
+        block_uid -> Int8,
+        asset_id -> Int8,
+        quantity -> Numeric,
+        decimals -> Int4,
+        name -> Text,
+        reissuable -> Bool,
+        script -> Nullable<Bytea>,
+        tx_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    balance_discrepancies (uid) {
+        uid -> Int8,
+        block_uid -> Int8,
+        address_id -> Int8,
+        asset_id -> Int8,
+        expected_amount -> Numeric,
+        amount_before -> Numeric,
+        tx_id -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     balance_history (uid) {
         uid -> Int8,
@@ -16,6 +43,26 @@ table! {
         address_id -> Int8,
         asset_id -> Int8,
         amount -> Nullable<Numeric>,
+        amount_before -> Nullable<Numeric>,
+        delta -> Nullable<Numeric>,
+        tx_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    balance_history_rollbacks (uid) {
+        uid -> Int8,
+        rollback_uid -> Int8,
+        bh_uid -> Nullable<Int8>,
+        block_uid -> Int8,
+        block_id -> Nullable<Text>,
+        height -> Int4,
+        address_id -> Int8,
+        asset_id -> Int8,
+        amount -> Nullable<Numeric>,
+        amount_before -> Nullable<Numeric>,
+        delta -> Nullable<Numeric>,
+        tx_id -> Nullable<Text>,
     }
 }
 
@@ -39,6 +86,41 @@ table! {
         max_height -> Nullable<Int4>,
         max_time_stamp -> Nullable<Int8>,
         deleted_blocks_data -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    data_entries_history (uid) {
+        uid -> Int8,
+        block_uid -> Int8,
+        address_id -> Int8,
+        key -> Text,
+        int_val -> Nullable<Int8>,
+        bool_val -> Nullable<Bool>,
+        bin_val -> Nullable<Bytea>,
+        str_val -> Nullable<Text>,
+        tx_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    ingest_filters (uid) {
+        uid -> Int8,
+        kind -> Text,
+        mode -> Text,
+        value -> Text,
+    }
+}
+
+table! {
+    leasing_history (uid) {
+        uid -> Int8,
+        block_uid -> Int8,
+        address_id -> Int8,
+        lease_in -> Numeric,
+        lease_out -> Numeric,
+        tx_id -> Nullable<Text>,
     }
 }
 
@@ -64,13 +146,24 @@ table! {
     }
 }
 
+joinable!(asset_history -> blocks_microblocks (block_uid));
+joinable!(balance_discrepancies -> blocks_microblocks (block_uid));
 joinable!(balance_history -> blocks_microblocks (block_uid));
+joinable!(balance_history_rollbacks -> blocks_rollbacks (rollback_uid));
+joinable!(data_entries_history -> blocks_microblocks (block_uid));
+joinable!(leasing_history -> blocks_microblocks (block_uid));
 
 allow_tables_to_appear_in_same_query!(
     asset_distribution_tasks,
+    asset_history,
+    balance_discrepancies,
     balance_history,
+    balance_history_rollbacks,
     blocks_microblocks,
     blocks_rollbacks,
+    data_entries_history,
+    ingest_filters,
+    leasing_history,
     safe_heights,
     unique_address,
     unique_assets,